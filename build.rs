@@ -4,7 +4,7 @@ fn main() -> Result<()> {
     println!("cargo:rerun-if-env-changed=CORE_BPF_PROGRAM_ID");
     println!("cargo:rerun-if-env-changed=CORE_BPF_TARGET");
 
-    let proto_base_path = std::path::PathBuf::from("protosol/proto");
+    let proto_base_path = std::path::PathBuf::from("proto");
 
     let protos = &[
         proto_base_path.join("invoke.proto"),
@@ -12,11 +12,15 @@ fn main() -> Result<()> {
         proto_base_path.join("txn.proto"),
         proto_base_path.join("elf.proto"),
         proto_base_path.join("shred.proto"),
+        proto_base_path.join("block.proto"),
+        proto_base_path.join("mini_txn.proto"),
+        proto_base_path.join("acct_serialize.proto"),
+        proto_base_path.join("txn_parse.proto"),
+        proto_base_path.join("compute_budget.proto"),
     ];
 
-    protos
-        .iter()
-        .for_each(|proto| println!("cargo:rerun-if-changed={}", proto.display()));
+    // Imported files are compiled along with the files importing them
+    println!("cargo:rerun-if-changed={}", proto_base_path.display());
 
     prost_build::compile_protos(protos, &[proto_base_path])?;
 
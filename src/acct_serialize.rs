@@ -0,0 +1,320 @@
+use crate::{
+    get_instr_accounts,
+    proto::{AcctSerializeContext, AcctSerializeEffects},
+    utils::{
+        err_map::instr_err_to_num,
+        vm::serialization::{create_instruction_transaction_context, extract_serialized_regions},
+    },
+    InstrContext,
+};
+use prost::Message;
+use solana_bpf_loader_program::serialization::{deserialize_parameters, serialize_parameters};
+use solana_sdk::{
+    account::{AccountSharedData, WritableAccount},
+    pubkey::Pubkey,
+    rent::Rent,
+    transaction_context::{IndexOfAccount, TransactionAccount, TransactionContext},
+};
+use std::ffi::c_int;
+
+#[no_mangle]
+pub unsafe extern "C" fn sol_compat_acct_serialize_v1(
+    out_ptr: *mut u8,
+    out_psz: *mut u64,
+    in_ptr: *mut u8,
+    in_sz: u64,
+) -> c_int {
+    let in_slice = std::slice::from_raw_parts(in_ptr, in_sz as usize);
+    let serialize_context = match AcctSerializeContext::decode(in_slice) {
+        Ok(context) => context,
+        Err(_) => return 0,
+    };
+
+    let serialize_effects = match execute_acct_serialize(serialize_context) {
+        Some(v) => v,
+        None => return 0,
+    };
+    let out_slice = std::slice::from_raw_parts_mut(out_ptr, (*out_psz) as usize);
+    let out_vec = serialize_effects.encode_to_vec();
+    if out_vec.len() > out_slice.len() {
+        return 0;
+    }
+    out_slice[..out_vec.len()].copy_from_slice(&out_vec);
+    *out_psz = out_vec.len() as u64;
+
+    1
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sol_compat_acct_deserialize_v1(
+    out_ptr: *mut u8,
+    out_psz: *mut u64,
+    in_ptr: *mut u8,
+    in_sz: u64,
+) -> c_int {
+    let in_slice = std::slice::from_raw_parts(in_ptr, in_sz as usize);
+    let serialize_context = match AcctSerializeContext::decode(in_slice) {
+        Ok(context) => context,
+        Err(_) => return 0,
+    };
+
+    let serialize_effects = match execute_acct_deserialize(serialize_context) {
+        Some(v) => v,
+        None => return 0,
+    };
+    let out_slice = std::slice::from_raw_parts_mut(out_ptr, (*out_psz) as usize);
+    let out_vec = serialize_effects.encode_to_vec();
+    if out_vec.len() > out_slice.len() {
+        return 0;
+    }
+    out_slice[..out_vec.len()].copy_from_slice(&out_vec);
+    *out_psz = out_vec.len() as u64;
+
+    1
+}
+
+/* Builds a transaction context with the instruction pushed onto the stack.
+The program account is reassigned to the requested loader, if any, since its
+owner selects between the aligned and the unaligned (deprecated loader)
+serializers. */
+fn setup_transaction_context(input: &mut AcctSerializeContext) -> Option<TransactionContext> {
+    let instr_ctx: InstrContext = input.instr_ctx.take()?.try_into().ok()?;
+
+    let mut transaction_accounts: Vec<TransactionAccount> = instr_ctx
+        .accounts
+        .iter()
+        .map(|(pubkey, account)| (*pubkey, AccountSharedData::from(account.clone())))
+        .collect();
+    let program_idx = transaction_accounts
+        .iter()
+        .position(|(pubkey, _)| *pubkey == instr_ctx.instruction.program_id)?;
+    if !input.loader_id.is_empty() {
+        let loader_id = Pubkey::new_from_array(input.loader_id.clone().try_into().ok()?);
+        transaction_accounts[program_idx].1.set_owner(loader_id);
+    }
+
+    let instruction_accounts =
+        get_instr_accounts(&transaction_accounts, &instr_ctx.instruction.accounts);
+    create_instruction_transaction_context(
+        &transaction_accounts,
+        Rent::default(),
+        program_idx as IndexOfAccount,
+        &instruction_accounts,
+        &instr_ctx.instruction.data,
+    )
+    .ok()
+}
+
+fn execute_acct_serialize(mut input: AcctSerializeContext) -> Option<AcctSerializeEffects> {
+    let transaction_context = setup_transaction_context(&mut input)?;
+    let instruction_context = transaction_context.get_current_instruction_context().ok()?;
+
+    let (parameter_bytes, regions, account_metadata) = match serialize_parameters(
+        &transaction_context,
+        instruction_context,
+        !input.direct_mapping,
+    ) {
+        Ok(v) => v,
+        Err(err) => {
+            return Some(AcctSerializeEffects {
+                result: instr_err_to_num(&err),
+                ..Default::default()
+            })
+        }
+    };
+
+    Some(AcctSerializeEffects {
+        result: 0,
+        serialized: parameter_bytes.as_slice().to_vec(),
+        input_data_regions: extract_serialized_regions(&regions),
+        account_metadata: account_metadata.iter().map(Into::into).collect(),
+        accounts: vec![],
+    })
+}
+
+/* Serializes the instruction, then deserializes the mutated buffer (or the
+serialized bytes themselves if no buffer is given) back into the accounts,
+like the BPF loader does once the program returns. With direct mapping,
+account data is not part of the buffer and is left as is. A mutated buffer
+must have the length of the serialized bytes, since the program cannot
+resize its input region. */
+fn execute_acct_deserialize(mut input: AcctSerializeContext) -> Option<AcctSerializeEffects> {
+    let mut transaction_context = setup_transaction_context(&mut input)?;
+    let instruction_context = transaction_context.get_current_instruction_context().ok()?;
+    let copy_account_data = !input.direct_mapping;
+
+    let (mut parameter_bytes, regions, account_metadata) =
+        match serialize_parameters(&transaction_context, instruction_context, copy_account_data) {
+            Ok(v) => v,
+            Err(err) => {
+                return Some(AcctSerializeEffects {
+                    result: instr_err_to_num(&err),
+                    ..Default::default()
+                })
+            }
+        };
+
+    if !input.mutated_buffer.is_empty() {
+        if input.mutated_buffer.len() != parameter_bytes.len() {
+            return None;
+        }
+        parameter_bytes
+            .as_slice_mut()
+            .copy_from_slice(&input.mutated_buffer);
+    }
+    // Deserialization may resize account data the regions point into
+    let input_data_regions = extract_serialized_regions(&regions);
+    let result = deserialize_parameters(
+        &transaction_context,
+        instruction_context,
+        copy_account_data,
+        parameter_bytes.as_slice(),
+        &account_metadata,
+    );
+
+    // The instruction must be popped before the accounts can be extracted.
+    // Balance checks are out of scope here, the instruction is always popped.
+    let _ = transaction_context.pop();
+
+    let keys: Vec<Pubkey> = (0..transaction_context.get_number_of_accounts())
+        .filter_map(|index| {
+            transaction_context
+                .get_key_of_account_at_index(index)
+                .ok()
+                .copied()
+        })
+        .collect();
+    let accounts = keys
+        .into_iter()
+        .zip(transaction_context.deconstruct_without_keys().ok()?)
+        .map(|account: TransactionAccount| account.into())
+        .collect();
+
+    Some(AcctSerializeEffects {
+        result: result
+            .as_ref()
+            .err()
+            .map(instr_err_to_num)
+            .unwrap_or_default(),
+        serialized: parameter_bytes.as_slice().to_vec(),
+        input_data_regions,
+        account_metadata: account_metadata.iter().map(Into::into).collect(),
+        accounts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{self, AcctState};
+    use crate::utils::test_utils::system_account;
+
+    fn deserialize_context(
+        program_id: &Pubkey,
+        address: &Pubkey,
+        mutated_buffer: Vec<u8>,
+    ) -> AcctSerializeContext {
+        AcctSerializeContext {
+            instr_ctx: Some(proto::InstrContext {
+                program_id: program_id.to_bytes().to_vec(),
+                accounts: vec![
+                    AcctState {
+                        data: vec![1, 2, 3],
+                        ..system_account(address, 42)
+                    },
+                    AcctState {
+                        owner: solana_sdk::bpf_loader::id().to_bytes().to_vec(),
+                        executable: true,
+                        ..system_account(program_id, 1)
+                    },
+                ],
+                instr_accounts: vec![proto::InstrAcct {
+                    index: 0,
+                    is_signer: true,
+                    is_writable: true,
+                }],
+                data: vec![9, 8],
+                cu_avail: 0,
+                epoch_context: None,
+                slot_context: None,
+                duplicate_account_policy: 0,
+                profile: false,
+                capture_input_region: false,
+            }),
+            loader_id: vec![],
+            direct_mapping: false,
+            mutated_buffer,
+        }
+    }
+
+    fn find_account<'a>(effects: &'a AcctSerializeEffects, address: &Pubkey) -> &'a AcctState {
+        effects
+            .accounts
+            .iter()
+            .find(|account| account.address == address.to_bytes())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_acct_deserialize_roundtrip() {
+        let program_id = Pubkey::new_unique();
+        let address = Pubkey::new_unique();
+        let serialized =
+            execute_acct_serialize(deserialize_context(&program_id, &address, vec![])).unwrap();
+
+        for mutated_buffer in [vec![], serialized.serialized.clone()] {
+            let effects = execute_acct_deserialize(deserialize_context(
+                &program_id,
+                &address,
+                mutated_buffer,
+            ))
+            .unwrap();
+            assert_eq!(effects.result, 0);
+            assert_eq!(effects.serialized, serialized.serialized);
+            assert_eq!(effects.input_data_regions, serialized.input_data_regions);
+            assert_eq!(effects.account_metadata, serialized.account_metadata);
+            let account = find_account(&effects, &address);
+            assert_eq!(account.lamports, 42);
+            assert_eq!(account.data, vec![1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn test_acct_deserialize_mutated_lamports() {
+        let program_id = Pubkey::new_unique();
+        let address = Pubkey::new_unique();
+        let mut mutated_buffer =
+            execute_acct_serialize(deserialize_context(&program_id, &address, vec![]))
+                .unwrap()
+                .serialized;
+        // Lamports of the first account in the aligned layout
+        mutated_buffer[80..88].copy_from_slice(&100u64.to_le_bytes());
+
+        let effects = execute_acct_deserialize(deserialize_context(
+            &program_id,
+            &address,
+            mutated_buffer.clone(),
+        ))
+        .unwrap();
+        assert_eq!(effects.result, 0);
+        assert_eq!(effects.serialized, mutated_buffer);
+        assert_eq!(effects.input_data_regions[0].content, mutated_buffer);
+        assert_eq!(find_account(&effects, &address).lamports, 100);
+    }
+
+    #[test]
+    fn test_acct_deserialize_short_buffer() {
+        let program_id = Pubkey::new_unique();
+        let address = Pubkey::new_unique();
+        let mut mutated_buffer =
+            execute_acct_serialize(deserialize_context(&program_id, &address, vec![]))
+                .unwrap()
+                .serialized;
+        mutated_buffer.pop();
+
+        assert_eq!(
+            execute_acct_deserialize(deserialize_context(&program_id, &address, mutated_buffer)),
+            None
+        );
+    }
+}
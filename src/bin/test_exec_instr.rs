@@ -67,6 +67,14 @@ fn exec(input: &PathBuf) -> bool {
     }
     let mut pruned_effects = effects.clone();
     pruned_effects.modified_accounts = modified_accounts;
+    // Diagnostics reported on top of the effects are not part of the fixtures
+    let mut expected = expected;
+    for effects in [&mut pruned_effects, &mut expected] {
+        effects.loaded_programs.clear();
+        effects.duplicate_account_policy = 0;
+        effects.accounts_resize_delta = 0;
+        effects.account_resizes.clear();
+    }
 
     let ok = pruned_effects == expected;
     if ok {
@@ -1,6 +1,6 @@
 use clap::Parser;
 use prost::Message;
-use solfuzz_agave::proto::TxnFixture;
+use solfuzz_agave::proto::{TxnFixture, TxnResult};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -9,6 +9,15 @@ struct Cli {
     inputs: Vec<PathBuf>,
 }
 
+/// Clears the harness diagnostics the fixtures do not record: the echoed
+/// duplicate account policy and the resize accounting, like the instruction
+/// fixture runner does.
+fn clear_unrecorded_fields(result: &mut TxnResult) {
+    result.duplicate_account_policy = 0;
+    result.accounts_resize_delta = 0;
+    result.account_resizes.clear();
+}
+
 fn exec(input: &PathBuf) -> bool {
     let blob = std::fs::read(input).unwrap();
     let fixture = TxnFixture::decode(&blob[..]).unwrap();
@@ -50,6 +59,10 @@ fn exec(input: &PathBuf) -> bool {
         }
     }
 
+    /* Ignore fields the fixtures do not record */
+    clear_unrecorded_fields(&mut expected);
+    clear_unrecorded_fields(&mut effects);
+
     let ok = effects == expected;
     if ok {
         println!("OK: {:?}", input);
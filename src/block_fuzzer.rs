@@ -0,0 +1,408 @@
+use crate::proto::{self, AcctState, BankCounters, BlockContext, BlockEffects, TxnResult};
+use crate::txn_fuzzer::{
+    build_versioned_transaction, message_build_error_result, parse_fee_collector,
+    processing_config, prune_resulting_state, sanitization_error_result, set_rollback_state,
+    setup_bank, MessageBuildError,
+};
+use crate::{dedup_accounts, resolve_duplicate_account_policy};
+use prost::Message;
+use solana_program::pubkey::Pubkey;
+use solana_runtime::bank::Bank;
+use solana_sdk::feature_set::FeatureSet;
+use solana_sdk::sysvar;
+use solana_sdk::transaction::TransactionVerificationMode;
+use solana_svm::transaction_error_metrics::TransactionErrorMetrics;
+use solana_timings::ExecuteTimings;
+use std::collections::HashSet;
+use std::ffi::c_int;
+
+#[no_mangle]
+pub unsafe extern "C" fn sol_compat_block_execute_v1(
+    out_ptr: *mut u8,
+    out_psz: *mut u64,
+    in_ptr: *mut u8,
+    in_sz: u64,
+) -> c_int {
+    if in_ptr.is_null() || in_sz == 0 {
+        return 0;
+    }
+    let in_slice = std::slice::from_raw_parts(in_ptr, in_sz as usize);
+    let block_context = match BlockContext::decode(in_slice) {
+        Ok(context) => context,
+        Err(_) => return 0, // Decode error
+    };
+
+    let block_effects = match execute_block(block_context) {
+        Some(value) => value,
+        None => return 0, // Data format error
+    };
+
+    let out_slice = std::slice::from_raw_parts_mut(out_ptr, (*out_psz) as usize);
+    let out_vec = block_effects.encode_to_vec();
+    if out_vec.len() > out_slice.len() {
+        return 0;
+    }
+
+    out_slice[..out_vec.len()].copy_from_slice(&out_vec);
+    *out_psz = out_vec.len() as u64;
+
+    1
+}
+
+/// Addresses whose final state is reported, in the order they were first seen.
+#[derive(Default)]
+struct ReferencedAccounts {
+    seen: HashSet<Pubkey>,
+    ordered: Vec<Pubkey>,
+}
+
+impl ReferencedAccounts {
+    fn insert(&mut self, pubkey: Pubkey) {
+        if pubkey != sysvar::instructions::id() && self.seen.insert(pubkey) {
+            self.ordered.push(pubkey);
+        }
+    }
+}
+
+/// Executes the slots of a block in order. All slots share the accounts of
+/// the block context, and each slot is a child of the previous one. Blocks
+/// whose transaction messages embed account states are rejected, since those
+/// would override the state carried between transactions.
+pub fn execute_block(context: BlockContext) -> Option<BlockEffects> {
+    let fd_features = context
+        .epoch_ctx
+        .as_ref()
+        .map(|ctx| ctx.features.clone().unwrap_or_default())
+        .unwrap_or_default();
+
+    let feature_set = FeatureSet::from(&fd_features);
+    let fee_collector = parse_fee_collector(&context.fee_collector)?;
+    let duplicate_account_policy =
+        resolve_duplicate_account_policy(context.duplicate_account_policy(), true);
+
+    /* Slots must be strictly increasing, each one is a child of the previous one */
+    if context
+        .slots
+        .windows(2)
+        .any(|slots| slots[0].slot >= slots[1].slot)
+    {
+        return None;
+    }
+    let first_slot = context.slots.first().map(|slot| slot.slot).unwrap_or(10); // Arbitrary default > 0
+
+    /* Accounts are only loaded from the block context */
+    if context
+        .slots
+        .iter()
+        .flat_map(|block_slot| &block_slot.batches)
+        .flat_map(|batch| &batch.txs)
+        .filter_map(|tx| tx.message.as_ref())
+        .any(|message| !message.account_shared_data.is_empty())
+    {
+        return None;
+    }
+
+    let accounts = context
+        .acct_states
+        .iter()
+        .map(|account| {
+            let pubkey = Pubkey::new_from_array(account.address.clone().try_into().ok()?);
+            Some((pubkey, account))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let accounts = dedup_accounts(accounts, duplicate_account_policy).ok()?;
+
+    let mut session = setup_bank(
+        &feature_set,
+        &accounts,
+        context.blockhash_queue.clone(),
+        first_slot,
+        &fee_collector,
+        None,
+    )?;
+
+    let mut referenced_accounts = ReferencedAccounts::default();
+    for (pubkey, _) in &accounts {
+        referenced_accounts.insert(*pubkey);
+    }
+
+    let mut txn_results = Vec::<TxnResult>::new();
+    for (index, block_slot) in context.slots.iter().enumerate() {
+        if index > 0 {
+            session.advance_to_slot(block_slot.slot, &fee_collector);
+        }
+        for batch in &block_slot.batches {
+            txn_results.extend(execute_batch(
+                &session.bank,
+                batch,
+                context.max_age as usize,
+                &mut referenced_accounts,
+            )?);
+        }
+    }
+    /* Freezing distributes the collected fees */
+    let bank = &session.bank;
+    bank.freeze();
+
+    for txn_result in txn_results.iter_mut() {
+        txn_result.duplicate_account_policy = duplicate_account_policy as i32;
+    }
+
+    let acct_states = referenced_accounts
+        .ordered
+        .iter()
+        .filter_map(|pubkey| {
+            bank.get_account(pubkey)
+                .map(|account| AcctState::from((*pubkey, account)))
+        })
+        .collect();
+
+    Some(BlockEffects {
+        txn_results,
+        acct_states,
+        counters: Some(BankCounters {
+            slot: bank.slot(),
+            transaction_count: bank.transaction_count(),
+            transaction_error_count: bank.transaction_error_count(),
+            signature_count: bank.signature_count(),
+            capitalization: bank.capitalization(),
+            collector_balance: bank.get_balance(&fee_collector),
+        }),
+    })
+}
+
+/// Executes and commits a batch of transactions, so later batches observe
+/// its effects. Transactions failing verification are reported without being
+/// part of the batch. Results are in the order of the batch transactions.
+fn execute_batch(
+    bank: &Bank,
+    batch: &proto::TxnBatch,
+    max_age: usize,
+    referenced_accounts: &mut ReferencedAccounts,
+) -> Option<Vec<TxnResult>> {
+    let mut txn_results = Vec::<Option<TxnResult>>::with_capacity(batch.txs.len());
+    let mut sanitized_transactions = Vec::with_capacity(batch.txs.len());
+    for tx in &batch.txs {
+        let versioned_transaction = match build_versioned_transaction(tx) {
+            Ok(versioned_transaction) => versioned_transaction,
+            Err(MessageBuildError::MissingMessage) => return None,
+            Err(error) => {
+                txn_results.push(Some(message_build_error_result(error)));
+                continue;
+            }
+        };
+        match bank.verify_transaction(
+            versioned_transaction,
+            TransactionVerificationMode::HashAndVerifyPrecompiles,
+        ) {
+            Ok(sanitized_transaction) => {
+                for pubkey in sanitized_transaction.message().account_keys().iter() {
+                    referenced_accounts.insert(*pubkey);
+                }
+                sanitized_transactions.push(sanitized_transaction);
+                txn_results.push(None);
+            }
+            Err(e) => txn_results.push(Some(sanitization_error_result(&e))),
+        }
+    }
+
+    let batch = bank.prepare_sanitized_batch(&sanitized_transactions);
+    let mut timings = ExecuteTimings::default();
+    let mut metrics = TransactionErrorMetrics::default();
+    let output = bank.load_and_execute_transactions(
+        &batch,
+        max_age,
+        &mut timings,
+        &mut metrics,
+        processing_config(bank, None, false),
+    );
+
+    let mut executed_results = output
+        .processing_results
+        .iter()
+        .zip(sanitized_transactions.iter())
+        .map(|(processing_result, sanitized_transaction)| {
+            let mut txn_result = TxnResult::from(processing_result);
+            set_rollback_state(&mut txn_result, processing_result, sanitized_transaction);
+            if let Some(resulting_state) = &mut txn_result.resulting_state {
+                prune_resulting_state(resulting_state, sanitized_transaction, bank);
+            }
+            txn_result
+        })
+        .collect::<Vec<_>>()
+        .into_iter();
+
+    bank.commit_transactions(
+        &sanitized_transactions,
+        output.processing_results,
+        &output.processed_counts,
+        &mut timings,
+    );
+
+    txn_results
+        .into_iter()
+        .map(|txn_result| txn_result.or_else(|| executed_results.next()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{
+        BlockSlot, CompiledInstruction, MessageHeader, SanitizedTransaction, TransactionMessage,
+        TxnBatch,
+    };
+    use crate::utils::test_utils::system_account;
+    use solana_program::hash::Hash;
+    use solana_sdk::signature::Signature;
+
+    fn transfer(
+        from: &Pubkey,
+        to: &Pubkey,
+        lamports: u64,
+        blockhash: &[u8],
+    ) -> SanitizedTransaction {
+        let mut data = vec![2, 0, 0, 0];
+        data.extend_from_slice(&lamports.to_le_bytes());
+        SanitizedTransaction {
+            message: Some(TransactionMessage {
+                is_legacy: true,
+                header: Some(MessageHeader {
+                    num_required_signatures: 1,
+                    num_readonly_signed_accounts: 0,
+                    num_readonly_unsigned_accounts: 1,
+                }),
+                account_keys: vec![
+                    from.to_bytes().to_vec(),
+                    to.to_bytes().to_vec(),
+                    vec![0; 32],
+                ],
+                account_shared_data: vec![],
+                instructions: vec![CompiledInstruction {
+                    program_id_index: 2,
+                    accounts: vec![0, 1],
+                    data,
+                }],
+                address_table_lookups: vec![],
+                recent_blockhash: blockhash.to_vec(),
+            }),
+            message_hash: Hash::new_unique().to_bytes().to_vec(),
+            is_simple_vote_tx: false,
+            signatures: vec![Signature::new_unique().as_ref().to_vec()],
+        }
+    }
+
+    #[test]
+    fn test_block_carries_state_across_slots() {
+        let payer = Pubkey::new_unique();
+        let first_recipient = Pubkey::new_unique();
+        let second_recipient = Pubkey::new_unique();
+        let blockhash = Hash::new_unique().to_bytes().to_vec();
+
+        // The second transaction is paid for with lamports received in the
+        // first one, in the previous slot
+        let context = BlockContext {
+            acct_states: vec![system_account(&payer, 10_000_000_000)],
+            blockhash_queue: vec![blockhash.clone()],
+            epoch_ctx: None,
+            slots: vec![
+                BlockSlot {
+                    slot: 10,
+                    batches: vec![TxnBatch {
+                        txs: vec![transfer(&payer, &first_recipient, 5_000_000, &blockhash)],
+                    }],
+                },
+                BlockSlot {
+                    slot: 11,
+                    batches: vec![TxnBatch {
+                        txs: vec![transfer(
+                            &first_recipient,
+                            &second_recipient,
+                            2_000_000,
+                            &blockhash,
+                        )],
+                    }],
+                },
+            ],
+            max_age: 150,
+            duplicate_account_policy: 0,
+            fee_collector: vec![],
+        };
+        let effects = execute_block(context).unwrap();
+
+        assert_eq!(effects.txn_results.len(), 2);
+        assert!(effects.txn_results.iter().all(|result| result.is_ok));
+        let second_recipient_state = effects
+            .acct_states
+            .iter()
+            .find(|account| account.address == second_recipient.to_bytes().to_vec())
+            .unwrap();
+        assert_eq!(second_recipient_state.lamports, 2_000_000);
+        assert_eq!(effects.counters.unwrap().slot, 11);
+    }
+
+    #[test]
+    fn test_block_fee_collector() {
+        let payer = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let fee_collector = Pubkey::new_unique();
+        let blockhash = Hash::new_unique().to_bytes().to_vec();
+
+        let context = BlockContext {
+            acct_states: vec![
+                system_account(&payer, 10_000_000_000),
+                system_account(&fee_collector, 1_000_000_000),
+            ],
+            blockhash_queue: vec![blockhash.clone()],
+            epoch_ctx: None,
+            slots: vec![BlockSlot {
+                slot: 10,
+                batches: vec![TxnBatch {
+                    txs: vec![transfer(&payer, &recipient, 5_000_000, &blockhash)],
+                }],
+            }],
+            max_age: 150,
+            duplicate_account_policy: 0,
+            fee_collector: fee_collector.to_bytes().to_vec(),
+        };
+        let effects = execute_block(context).unwrap();
+
+        assert!(effects.txn_results[0].is_ok);
+        let collector_balance = effects.counters.unwrap().collector_balance;
+        assert!(collector_balance > 1_000_000_000);
+        let collector_state = effects
+            .acct_states
+            .iter()
+            .find(|account| account.address == fee_collector.to_bytes().to_vec())
+            .unwrap();
+        assert_eq!(collector_state.lamports, collector_balance);
+    }
+
+    #[test]
+    fn test_block_rejects_embedded_account_states() {
+        let payer = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let blockhash = Hash::new_unique().to_bytes().to_vec();
+
+        let mut tx = transfer(&payer, &recipient, 5_000_000, &blockhash);
+        tx.message
+            .as_mut()
+            .unwrap()
+            .account_shared_data
+            .push(system_account(&recipient, 1_000_000));
+        let context = BlockContext {
+            acct_states: vec![system_account(&payer, 10_000_000_000)],
+            blockhash_queue: vec![blockhash],
+            epoch_ctx: None,
+            slots: vec![BlockSlot {
+                slot: 10,
+                batches: vec![TxnBatch { txs: vec![tx] }],
+            }],
+            max_age: 150,
+            duplicate_account_policy: 0,
+            fee_collector: vec![],
+        };
+
+        assert_eq!(execute_block(context), None);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::proto::{ComputeBudgetContext, ComputeBudgetEffects};
+use crate::txn_fuzzer::TransactionErrorFields;
+use prost::Message;
+use solana_program::pubkey::Pubkey;
+use solana_runtime_transaction::instructions_processor::process_compute_budget_instructions;
+use solana_sdk::instruction::CompiledInstruction;
+use solana_svm_transaction::instruction::SVMInstruction;
+use std::ffi::c_int;
+
+#[no_mangle]
+pub unsafe extern "C" fn sol_compat_compute_budget_v1(
+    out_ptr: *mut u8,
+    out_psz: *mut u64,
+    in_ptr: *mut u8,
+    in_sz: u64,
+) -> c_int {
+    let in_slice = std::slice::from_raw_parts(in_ptr, in_sz as usize);
+    let compute_budget_context = match ComputeBudgetContext::decode(in_slice) {
+        Ok(context) => context,
+        Err(_) => return 0,
+    };
+
+    let compute_budget_effects = match process_compute_budget(&compute_budget_context) {
+        Some(effects) => effects,
+        None => return 0, // Data format error
+    };
+
+    let out_slice = std::slice::from_raw_parts_mut(out_ptr, (*out_psz) as usize);
+    let out_bytes = compute_budget_effects.encode_to_vec();
+    if out_bytes.len() > out_slice.len() {
+        return 0;
+    }
+    out_slice[..out_bytes.len()].copy_from_slice(&out_bytes);
+    *out_psz = out_bytes.len() as u64;
+
+    1
+}
+
+/* Resolves the compute budget limits requested by the instructions of a
+message, like the bank does before loading a transaction. Program ids are
+looked up in the static account keys, since programs cannot be loaded from
+address lookup tables. */
+pub fn process_compute_budget(input: &ComputeBudgetContext) -> Option<ComputeBudgetEffects> {
+    let account_keys = input
+        .account_keys
+        .iter()
+        .map(|key| Some(Pubkey::new_from_array(key.clone().try_into().ok()?)))
+        .collect::<Option<Vec<_>>>()?;
+    let instructions = input
+        .instructions
+        .iter()
+        .map(|instruction| {
+            let program_id = account_keys.get(instruction.program_id_index as usize)?;
+            Some((program_id, CompiledInstruction::try_from(instruction).ok()?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let compute_budget_limits = match process_compute_budget_instructions(
+        instructions
+            .iter()
+            .map(|(program_id, instruction)| (*program_id, SVMInstruction::from(instruction))),
+    ) {
+        Ok(compute_budget_limits) => compute_budget_limits,
+        Err(error) => {
+            let error_fields = TransactionErrorFields::from(Some(&error));
+            return Some(ComputeBudgetEffects {
+                status: error_fields.status,
+                instruction_error: error_fields.instruction_error,
+                instruction_error_index: error_fields.instruction_error_index,
+                custom_error: error_fields.custom_error,
+                ..ComputeBudgetEffects::default()
+            });
+        }
+    };
+
+    Some(ComputeBudgetEffects {
+        compute_unit_limit: compute_budget_limits.compute_unit_limit,
+        compute_unit_price: compute_budget_limits.compute_unit_price,
+        heap_size: compute_budget_limits.updated_heap_bytes,
+        loaded_accounts_data_size_limit: compute_budget_limits.loaded_accounts_bytes.get(),
+        ..ComputeBudgetEffects::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto;
+    use crate::utils::err_map::txn_err_to_num;
+    use solana_sdk::compute_budget::ComputeBudgetInstruction;
+    use solana_sdk::transaction::TransactionError;
+
+    #[test]
+    fn test_compute_budget() {
+        let compute_budget_program = solana_sdk::compute_budget::id();
+        let mut input = ComputeBudgetContext {
+            account_keys: vec![compute_budget_program.to_bytes().to_vec()],
+            instructions: [
+                ComputeBudgetInstruction::set_compute_unit_limit(300_000),
+                ComputeBudgetInstruction::set_compute_unit_price(1_000),
+                ComputeBudgetInstruction::request_heap_frame(64 * 1024),
+            ]
+            .into_iter()
+            .map(|instruction| proto::CompiledInstruction {
+                program_id_index: 0,
+                accounts: vec![],
+                data: instruction.data,
+            })
+            .collect(),
+        };
+        let effects = process_compute_budget(&input).unwrap();
+        assert_eq!(effects.status, 0);
+        assert_eq!(effects.compute_unit_limit, 300_000);
+        assert_eq!(effects.compute_unit_price, 1_000);
+        assert_eq!(effects.heap_size, 64 * 1024);
+        assert!(effects.loaded_accounts_data_size_limit > 0);
+
+        // Requesting the compute unit limit twice fails on the second request
+        input.instructions.push(proto::CompiledInstruction {
+            program_id_index: 0,
+            accounts: vec![],
+            data: ComputeBudgetInstruction::set_compute_unit_limit(1).data,
+        });
+        let effects = process_compute_budget(&input).unwrap();
+        assert_eq!(
+            effects.status,
+            txn_err_to_num(&TransactionError::DuplicateInstruction(3))
+        );
+        assert_eq!(effects.instruction_error_index, 3);
+        assert_eq!(effects.compute_unit_limit, 0);
+
+        // Program ids must be static account keys
+        input.instructions[0].program_id_index = 1;
+        assert!(process_compute_budget(&input).is_none());
+    }
+}
@@ -1,8 +1,13 @@
 #![allow(clippy::missing_safety_doc)]
 
+mod acct_serialize;
+pub mod block_fuzzer;
+pub mod compute_budget;
 pub mod elf_loader;
+pub mod mini_txn;
 mod shred_parse;
 pub mod txn_fuzzer;
+pub mod txn_parse;
 pub mod utils;
 mod vm_cpi_syscall;
 mod vm_interp;
@@ -20,7 +25,7 @@ use solana_program_runtime::loaded_programs::ProgramCacheForTxBatch;
 use solana_program_runtime::loaded_programs::ProgramRuntimeEnvironments;
 use solana_program_runtime::sysvar_cache::SysvarCache;
 use solana_sdk::account::{Account, AccountSharedData, ReadableAccount};
-use solana_sdk::clock::Clock;
+use solana_sdk::clock::{Clock, Slot};
 use solana_sdk::epoch_schedule::EpochSchedule;
 use solana_sdk::feature_set::*;
 use solana_sdk::instruction::AccountMeta;
@@ -39,11 +44,16 @@ use solana_sdk::transaction_context::{
 use solana_svm::program_loader;
 use solana_timings::ExecuteTimings;
 
+use crate::proto::DuplicateAccountPolicy;
+use crate::utils::entrypoints::{self, observe_entrypoint};
 use crate::utils::err_map::instr_err_to_num;
 use crate::utils::feature_u64;
+use crate::utils::loaded_programs::{describe_program_cache_entry, LoadedProgramInfo};
+use crate::utils::profile;
 use solana_svm::transaction_processing_callback::TransactionProcessingCallback;
 use solfuzz_agave_macro::load_core_bpf_program;
-use std::collections::HashSet;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::ffi::c_int;
 use std::sync::Arc;
@@ -279,6 +289,9 @@ pub enum Error {
 
     #[error("Invalid fixture output")]
     InvalidFixtureOutput,
+
+    #[error("Duplicate account address")]
+    DuplicateAccount,
 }
 
 pub struct InstrContext {
@@ -289,6 +302,9 @@ pub struct InstrContext {
     pub rent_collector: RentCollector,
     pub last_blockhash: Hash,
     pub lamports_per_signature: u64,
+    pub duplicate_account_policy: DuplicateAccountPolicy,
+    pub profile: bool,
+    pub capture_input_region: bool,
 }
 
 impl TransactionProcessingCallback for InstrContext {
@@ -345,26 +361,18 @@ impl TryFrom<proto::InstrContext> for InstrContext {
             .map(|fs| fs.into())
             .unwrap_or_default();
 
+        let duplicate_account_policy =
+            resolve_duplicate_account_policy(input.duplicate_account_policy(), false);
+
         let accounts: Vec<(Pubkey, Account)> = input
             .accounts
             .into_iter()
             .map(|acct_state| acct_state.try_into())
             .collect::<Result<Vec<_>, _>>()?;
 
-        let instruction_accounts = input
-            .instr_accounts
-            .into_iter()
-            .map(|acct| {
-                if acct.index as usize >= accounts.len() {
-                    return Err(Error::AccountMissing);
-                }
-                Ok(AccountMeta {
-                    pubkey: accounts[acct.index as usize].0,
-                    is_signer: acct.is_signer,
-                    is_writable: acct.is_writable,
-                })
-            })
-            .collect::<Result<Vec<_>, _>>()?;
+        // Instruction accounts refer to the accounts by index, resolve them
+        // before the harnesses remove duplicates
+        let instruction_accounts = get_instr_account_metas(&accounts, input.instr_accounts)?;
 
         let instruction = StableInstruction {
             accounts: instruction_accounts.into(),
@@ -380,10 +388,34 @@ impl TryFrom<proto::InstrContext> for InstrContext {
             rent_collector: RentCollector::default(),
             last_blockhash: Hash::default(),
             lamports_per_signature: 0,
+            duplicate_account_policy,
+            profile: input.profile,
+            capture_input_region: input.capture_input_region,
         })
     }
 }
 
+/// Resolves the instruction accounts of a protobuf instruction, which refer to
+/// `accounts` by index, into account metas.
+pub fn get_instr_account_metas(
+    accounts: &[(Pubkey, Account)],
+    instr_accounts: Vec<proto::InstrAcct>,
+) -> Result<Vec<AccountMeta>, Error> {
+    instr_accounts
+        .into_iter()
+        .map(|acct| {
+            if acct.index as usize >= accounts.len() {
+                return Err(Error::AccountMissing);
+            }
+            Ok(AccountMeta {
+                pubkey: accounts[acct.index as usize].0,
+                is_signer: acct.is_signer,
+                is_writable: acct.is_writable,
+            })
+        })
+        .collect()
+}
+
 pub fn get_instr_accounts(
     txn_accounts: &[TransactionAccount],
     acct_metas: &StableVec<AccountMeta>,
@@ -419,6 +451,17 @@ pub struct InstrEffects {
     pub modified_accounts: Vec<(Pubkey, Account)>,
     pub cu_avail: u64,
     pub return_data: Vec<u8>,
+    pub loaded_programs: Vec<LoadedProgramInfo>,
+    pub duplicate_account_policy: DuplicateAccountPolicy,
+    /// Net change of the accounts data size, as tracked by the transaction context
+    pub accounts_resize_delta: i64,
+    /// Data length change of every resized account
+    pub account_resizes: Vec<(Pubkey, i64)>,
+    /// Only set if profiling was requested
+    pub profile: Option<proto::ExecutionProfile>,
+    /// Input region handed to the VM, only set if its capture was requested
+    /// and the program is a BPF program
+    pub input_region: Option<proto::SerializedInput>,
 }
 
 impl From<InstrEffects> for proto::InstrEffects {
@@ -445,6 +488,19 @@ impl From<InstrEffects> for proto::InstrEffects {
                 .collect(),
             cu_avail: val.cu_avail,
             return_data: val.return_data,
+            loaded_programs: val.loaded_programs.into_iter().map(Into::into).collect(),
+            duplicate_account_policy: val.duplicate_account_policy as i32,
+            accounts_resize_delta: val.accounts_resize_delta,
+            account_resizes: val
+                .account_resizes
+                .into_iter()
+                .map(|(pubkey, data_len_delta)| proto::AccountResize {
+                    address: pubkey.to_bytes().to_vec(),
+                    data_len_delta,
+                })
+                .collect(),
+            profile: val.profile,
+            input_region: val.input_region,
         }
     }
 }
@@ -458,13 +514,53 @@ pub fn execute_instr_proto(input: proto::InstrContext) -> Option<proto::InstrEff
     instr_effects.map(Into::into)
 }
 
+/// Resolves an unspecified duplicate account policy to the harness default:
+/// transactions keep the first account state like FD does, every other
+/// harness rejects duplicate account loads.
+pub fn resolve_duplicate_account_policy(
+    policy: DuplicateAccountPolicy,
+    is_txn: bool,
+) -> DuplicateAccountPolicy {
+    match policy {
+        DuplicateAccountPolicy::Unspecified if is_txn => DuplicateAccountPolicy::FirstWins,
+        DuplicateAccountPolicy::Unspecified => DuplicateAccountPolicy::Reject,
+        policy => policy,
+    }
+}
+
+/// Removes account states sharing an address according to `policy`. The
+/// surviving state keeps the position of the first occurrence.
+pub fn dedup_accounts<T>(
+    accounts: Vec<(Pubkey, T)>,
+    policy: DuplicateAccountPolicy,
+) -> Result<Vec<(Pubkey, T)>, Error> {
+    let mut positions = HashMap::<Pubkey, usize>::with_capacity(accounts.len());
+    let mut deduped = Vec::<(Pubkey, T)>::with_capacity(accounts.len());
+    for (pubkey, account) in accounts {
+        match positions.entry(pubkey) {
+            Entry::Vacant(entry) => {
+                entry.insert(deduped.len());
+                deduped.push((pubkey, account));
+            }
+            Entry::Occupied(entry) => match policy {
+                DuplicateAccountPolicy::FirstWins => {}
+                DuplicateAccountPolicy::LastWins => deduped[*entry.get()].1 = account,
+                DuplicateAccountPolicy::Reject | DuplicateAccountPolicy::Unspecified => {
+                    return Err(Error::DuplicateAccount)
+                }
+            },
+        }
+    }
+    Ok(deduped)
+}
+
 fn load_builtins(cache: &mut ProgramCacheForTxBatch) -> HashSet<Pubkey> {
     cache.replenish(
         solana_sdk::address_lookup_table::program::id(),
         Arc::new(ProgramCacheEntry::new_builtin(
             0u64,
             0usize,
-            solana_address_lookup_table_program::processor::Entrypoint::vm,
+            observe_entrypoint(solana_address_lookup_table_program::processor::Entrypoint::vm),
         )),
     );
     cache.replenish(
@@ -472,7 +568,7 @@ fn load_builtins(cache: &mut ProgramCacheForTxBatch) -> HashSet<Pubkey> {
         Arc::new(ProgramCacheEntry::new_builtin(
             0u64,
             0usize,
-            solana_bpf_loader_program::Entrypoint::vm,
+            observe_entrypoint(solana_bpf_loader_program::Entrypoint::vm),
         )),
     );
     cache.replenish(
@@ -480,7 +576,7 @@ fn load_builtins(cache: &mut ProgramCacheForTxBatch) -> HashSet<Pubkey> {
         Arc::new(ProgramCacheEntry::new_builtin(
             0u64,
             0usize,
-            solana_bpf_loader_program::Entrypoint::vm,
+            observe_entrypoint(solana_bpf_loader_program::Entrypoint::vm),
         )),
     );
     cache.replenish(
@@ -488,7 +584,7 @@ fn load_builtins(cache: &mut ProgramCacheForTxBatch) -> HashSet<Pubkey> {
         Arc::new(ProgramCacheEntry::new_builtin(
             0u64,
             0usize,
-            solana_bpf_loader_program::Entrypoint::vm,
+            observe_entrypoint(solana_bpf_loader_program::Entrypoint::vm),
         )),
     );
     cache.replenish(
@@ -496,7 +592,7 @@ fn load_builtins(cache: &mut ProgramCacheForTxBatch) -> HashSet<Pubkey> {
         Arc::new(ProgramCacheEntry::new_builtin(
             0u64,
             0usize,
-            solana_compute_budget_program::Entrypoint::vm,
+            observe_entrypoint(solana_compute_budget_program::Entrypoint::vm),
         )),
     );
     cache.replenish(
@@ -504,7 +600,7 @@ fn load_builtins(cache: &mut ProgramCacheForTxBatch) -> HashSet<Pubkey> {
         Arc::new(ProgramCacheEntry::new_builtin(
             0u64,
             0usize,
-            solana_config_program::config_processor::Entrypoint::vm,
+            observe_entrypoint(solana_config_program::config_processor::Entrypoint::vm),
         )),
     );
     cache.replenish(
@@ -512,7 +608,7 @@ fn load_builtins(cache: &mut ProgramCacheForTxBatch) -> HashSet<Pubkey> {
         Arc::new(ProgramCacheEntry::new_builtin(
             0u64,
             0usize,
-            solana_stake_program::stake_instruction::Entrypoint::vm,
+            observe_entrypoint(solana_stake_program::stake_instruction::Entrypoint::vm),
         )),
     );
     cache.replenish(
@@ -520,7 +616,7 @@ fn load_builtins(cache: &mut ProgramCacheForTxBatch) -> HashSet<Pubkey> {
         Arc::new(ProgramCacheEntry::new_builtin(
             0u64,
             0usize,
-            solana_system_program::system_processor::Entrypoint::vm,
+            observe_entrypoint(solana_system_program::system_processor::Entrypoint::vm),
         )),
     );
     cache.replenish(
@@ -528,7 +624,7 @@ fn load_builtins(cache: &mut ProgramCacheForTxBatch) -> HashSet<Pubkey> {
         Arc::new(ProgramCacheEntry::new_builtin(
             0u64,
             0usize,
-            solana_vote_program::vote_processor::Entrypoint::vm,
+            observe_entrypoint(solana_vote_program::vote_processor::Entrypoint::vm),
         )),
     );
     cache.replenish(
@@ -536,7 +632,7 @@ fn load_builtins(cache: &mut ProgramCacheForTxBatch) -> HashSet<Pubkey> {
         Arc::new(ProgramCacheEntry::new_builtin(
             0u64,
             0usize,
-            solana_zk_elgamal_proof_program::Entrypoint::vm,
+            observe_entrypoint(solana_zk_elgamal_proof_program::Entrypoint::vm),
         )),
     );
 
@@ -559,18 +655,29 @@ fn load_builtins(cache: &mut ProgramCacheForTxBatch) -> HashSet<Pubkey> {
     builtins
 }
 
-fn execute_instr(mut input: InstrContext) -> Option<InstrEffects> {
-    // TODO this shouldn't be default
-    let compute_budget = ComputeBudget {
-        compute_unit_limit: input.cu_avail,
-        ..ComputeBudget::default()
-    };
-
+/// Builds the sysvar cache for the instruction-level harnesses. Sysvars are
+/// read from the input accounts first, missing ones fall back to defaults.
+fn setup_sysvar_cache(accounts: &[(Pubkey, Account)], slot: Option<Slot>) -> SysvarCache {
     let mut sysvar_cache = SysvarCache::default();
 
-    // First try populating sysvars from accounts list
+    // A slot context takes precedence over the slot of the clock sysvar account
+    if let Some(slot) = slot {
+        let clock = accounts
+            .iter()
+            .find(|(pubkey, account)| *pubkey == Clock::id() && account.lamports > 0)
+            .and_then(|(_, account)| bincode::deserialize::<Clock>(&account.data).ok())
+            .unwrap_or_default();
+        let clock_data = bincode::serialize(&Clock { slot, ..clock }).unwrap();
+        sysvar_cache.fill_missing_entries(|pubkey, callbackback| {
+            if *pubkey == Clock::id() {
+                callbackback(&clock_data);
+            }
+        });
+    }
+
+    // Then try populating sysvars from accounts list
     sysvar_cache.fill_missing_entries(|pubkey, callbackback| {
-        if let Some(account) = input.accounts.iter().find(|(key, _)| key == pubkey) {
+        if let Some(account) = accounts.iter().find(|(key, _)| key == pubkey) {
             if account.1.lamports > 0 {
                 callbackback(&account.1.data);
             }
@@ -601,17 +708,137 @@ fn execute_instr(mut input: InstrContext) -> Option<InstrEffects> {
         }
     });
 
+    sysvar_cache
+}
+
+/// Rent values outside of these bounds are rejected by Firedancer.
+fn is_rent_in_bounds(rent: &Rent) -> bool {
+    rent.lamports_per_byte_year <= u32::MAX.into()
+        && rent.exemption_threshold <= 999.0
+        && rent.exemption_threshold >= 0.0
+        && rent.burn_percent <= 100
+}
+
+fn create_program_runtime_environments(
+    feature_set: &FeatureSet,
+    compute_budget: &ComputeBudget,
+) -> ProgramRuntimeEnvironments {
+    let program_runtime_environment_v1 =
+        solana_bpf_loader_program::syscalls::create_program_runtime_environment_v1(
+            feature_set,
+            compute_budget,
+            false, /* deployment */
+            false, /* debugging_features */
+        )
+        .unwrap();
+    ProgramRuntimeEnvironments {
+        program_runtime_v1: Arc::new(program_runtime_environment_v1),
+        ..ProgramRuntimeEnvironments::default()
+    }
+}
+
+fn is_bpf_loader(owner: &Pubkey) -> bool {
+    solana_sdk::loader_v4::check_id(owner)
+        || solana_sdk::bpf_loader_deprecated::check_id(owner)
+        || solana_sdk::bpf_loader::check_id(owner)
+        || solana_sdk::bpf_loader_upgradeable::check_id(owner)
+}
+
+/// Loads the executable accounts owned by one of the BPF loaders into the
+/// program cache, the same way the SVM does before executing a transaction.
+/// Returns how each executable account ended up in the program cache.
+fn load_bpf_programs<CB: TransactionProcessingCallback>(
+    callbacks: &CB,
+    accounts: &[(Pubkey, Account)],
+    environments: &ProgramRuntimeEnvironments,
+    slot: Slot,
+    program_cache_for_tx_batch: &mut ProgramCacheForTxBatch,
+) -> Vec<LoadedProgramInfo> {
+    let mut loaded_programs = Vec::new();
+    for acc in accounts {
+        if !acc.1.executable {
+            continue;
+        }
+        if let Some(entry) = program_cache_for_tx_batch.find(&acc.0) {
+            // Builtins are already in the cache
+            loaded_programs.push(describe_program_cache_entry(
+                callbacks,
+                environments,
+                &acc.0,
+                slot,
+                &entry,
+                &entry,
+            ));
+            continue;
+        }
+        // load_program_with_pubkey expects the owner to be one of the bpf loader
+        if !is_bpf_loader(&acc.1.owner) {
+            loaded_programs.push(LoadedProgramInfo::not_loaded(
+                acc.0,
+                "program account is not owned by a BPF loader",
+            ));
+            continue;
+        }
+        // https://github.com/anza-xyz/agave/blob/af6930da3a99fd0409d3accd9bbe449d82725bd6/svm/src/program_loader.rs#L124
+        /* pub fn load_program_with_pubkey<CB: TransactionProcessingCallback, FG: ForkGraph>(
+            callbacks: &CB,
+            program_cache: &ProgramCache<FG>,
+            pubkey: &Pubkey,
+            slot: Slot,
+            effective_epoch: Epoch,
+            epoch_schedule: &EpochSchedule,
+            reload: bool,
+        ) -> Option<Arc<ProgramCacheEntry>> { */
+        let Some(loaded_program) =
+            program_loader::load_program_with_pubkey(callbacks, environments, &acc.0, slot, false)
+        else {
+            loaded_programs.push(LoadedProgramInfo::not_loaded(
+                acc.0,
+                "program loader did not return a cache entry",
+            ));
+            continue;
+        };
+        program_cache_for_tx_batch.replenish(acc.0, loaded_program.clone());
+
+        // Lookups go through the batch cache, which turns programs that are
+        // not effective yet into DelayVisibility tombstones
+        let entry = program_cache_for_tx_batch
+            .find(&acc.0)
+            .unwrap_or_else(|| loaded_program.clone());
+        loaded_programs.push(describe_program_cache_entry(
+            callbacks,
+            environments,
+            &acc.0,
+            slot,
+            &entry,
+            &loaded_program,
+        ));
+    }
+    loaded_programs
+}
+
+fn execute_instr(mut input: InstrContext) -> Option<InstrEffects> {
+    input.accounts = dedup_accounts(
+        std::mem::take(&mut input.accounts),
+        input.duplicate_account_policy,
+    )
+    .ok()?;
+
+    // TODO this shouldn't be default
+    let compute_budget = ComputeBudget {
+        compute_unit_limit: input.cu_avail,
+        ..ComputeBudget::default()
+    };
+
+    let sysvar_cache = setup_sysvar_cache(&input.accounts, None);
+
     let clock = sysvar_cache.get_clock().unwrap();
     let epoch_schedule = sysvar_cache.get_epoch_schedule().unwrap();
 
     // Add checks for rent boundaries
     let rent_ = sysvar_cache.get_rent().unwrap();
     let rent = (*rent_).clone();
-    if rent.lamports_per_byte_year > u32::MAX.into()
-        || rent.exemption_threshold > 999.0
-        || rent.exemption_threshold < 0.0
-        || rent.burn_percent > 100
-    {
+    if !is_rent_in_bounds(&rent) {
         return None;
     };
 
@@ -648,18 +875,10 @@ fn execute_instr(mut input: InstrContext) -> Option<InstrEffects> {
         return None;
     }
 
-    let program_runtime_environment_v1 =
-        solana_bpf_loader_program::syscalls::create_program_runtime_environment_v1(
-            &input.feature_set,
-            &compute_budget,
-            false, /* deployment */
-            false, /* debugging_features */
-        )
-        .unwrap();
-    let environments = ProgramRuntimeEnvironments {
-        program_runtime_v1: Arc::new(program_runtime_environment_v1),
-        ..ProgramRuntimeEnvironments::default()
-    };
+    let mut environments = create_program_runtime_environments(&input.feature_set, &compute_budget);
+    if input.profile {
+        environments = profile::profile_environments(&environments);
+    }
     program_cache_for_tx_batch.environments = environments.clone();
     program_cache_for_tx_batch.upcoming_environments = Some(environments.clone());
 
@@ -677,44 +896,13 @@ fn execute_instr(mut input: InstrContext) -> Option<InstrEffects> {
     input.rent_collector.epoch_schedule = (*epoch_schedule).clone();
     input.rent_collector.rent = (*rent_).clone();
 
-    let mut newly_loaded_programs = HashSet::<Pubkey>::new();
-
-    for acc in &input.accounts {
-        // FD rejects duplicate account loads
-        if !newly_loaded_programs.insert(acc.0) {
-            return None;
-        }
-
-        if acc.1.executable && program_cache_for_tx_batch.find(&acc.0).is_none() {
-            // load_program_with_pubkey expects the owner to be one of the bpf loader
-            if !solana_sdk::loader_v4::check_id(&acc.1.owner)
-                && !solana_sdk::bpf_loader_deprecated::check_id(&acc.1.owner)
-                && !solana_sdk::bpf_loader::check_id(&acc.1.owner)
-                && !solana_sdk::bpf_loader_upgradeable::check_id(&acc.1.owner)
-            {
-                continue;
-            }
-            // https://github.com/anza-xyz/agave/blob/af6930da3a99fd0409d3accd9bbe449d82725bd6/svm/src/program_loader.rs#L124
-            /* pub fn load_program_with_pubkey<CB: TransactionProcessingCallback, FG: ForkGraph>(
-                callbacks: &CB,
-                program_cache: &ProgramCache<FG>,
-                pubkey: &Pubkey,
-                slot: Slot,
-                effective_epoch: Epoch,
-                epoch_schedule: &EpochSchedule,
-                reload: bool,
-            ) -> Option<Arc<ProgramCacheEntry>> { */
-            if let Some(loaded_program) = program_loader::load_program_with_pubkey(
-                &input,
-                &environments,
-                &acc.0,
-                clock.slot,
-                false,
-            ) {
-                program_cache_for_tx_batch.replenish(acc.0, loaded_program);
-            }
-        }
-    }
+    let loaded_programs = load_bpf_programs(
+        &input,
+        &input.accounts,
+        &environments,
+        clock.slot,
+        &mut program_cache_for_tx_batch,
+    );
 
     let log_collector = LogCollector::new_ref();
     let env_config = EnvironmentConfig::new(
@@ -792,9 +980,19 @@ fn execute_instr(mut input: InstrContext) -> Option<InstrEffects> {
             modified_accounts: vec![],
             cu_avail: input.cu_avail,
             return_data: vec![],
+            loaded_programs,
+            duplicate_account_policy: input.duplicate_account_policy,
+            accounts_resize_delta: 0,
+            account_resizes: vec![],
+            profile: None,
+            input_region: None,
         });
     }
 
+    if input.capture_input_region {
+        entrypoints::start_capturing_input_region();
+    }
+    entrypoints::start_recording_account_resizes();
     let result = invoke_context.process_instruction(
         &input.instruction.data,
         &instruction_accounts,
@@ -802,8 +1000,23 @@ fn execute_instr(mut input: InstrContext) -> Option<InstrEffects> {
         &mut compute_units_consumed,
         &mut timings,
     );
+    let account_resizes = entrypoints::take_account_resizes();
+    let input_region = entrypoints::take_input_region();
+
+    let profile = input.profile.then(|| profile::take_profile(&timings));
 
     let return_data = transaction_context.get_return_data().1.to_vec();
+    let accounts_resize_delta = transaction_context
+        .accounts_resize_delta()
+        .unwrap_or_default();
+
+    let modified_accounts: Vec<(Pubkey, Account)> = transaction_context
+        .deconstruct_without_keys()
+        .unwrap()
+        .into_iter()
+        .enumerate()
+        .map(|(index, data)| (transaction_accounts[index].0, data.into()))
+        .collect();
 
     Some(InstrEffects {
         custom_err: if let Err(InstructionError::Custom(x)) = result {
@@ -812,15 +1025,15 @@ fn execute_instr(mut input: InstrContext) -> Option<InstrEffects> {
             None
         },
         result: result.err(),
-        modified_accounts: transaction_context
-            .deconstruct_without_keys()
-            .unwrap()
-            .into_iter()
-            .enumerate()
-            .map(|(index, data)| (transaction_accounts[index].0, data.into()))
-            .collect(),
+        modified_accounts,
         cu_avail: input.cu_avail - compute_units_consumed,
         return_data,
+        loaded_programs,
+        duplicate_account_policy: input.duplicate_account_policy,
+        accounts_resize_delta,
+        account_resizes,
+        profile,
+        input_region,
     })
 }
 
@@ -900,7 +1113,9 @@ pub unsafe extern "C" fn sol_compat_get_metadata_v1() -> *const SolCompatMetadat
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn sol_compat_fini() {}
+pub unsafe extern "C" fn sol_compat_fini() {
+    txn_fuzzer::remove_accounts_db_dirs();
+}
 
 #[no_mangle]
 pub unsafe extern "C" fn sol_compat_instr_execute_v1(
@@ -989,6 +1204,9 @@ mod tests {
             cu_avail: 10000u64,
             epoch_context: None,
             slot_context: None,
+            duplicate_account_policy: 0,
+            profile: false,
+            capture_input_region: false,
         };
         let output = execute_instr_proto(input);
         assert_eq!(
@@ -1027,7 +1245,42 @@ mod tests {
                 ],
                 cu_avail: 9850u64,
                 return_data: vec![],
+                loaded_programs: vec![proto::LoadedProgram {
+                    program_id: vec![0u8; 32],
+                    kind: proto::ProgramCacheEntryKind::Builtin as i32,
+                    deployment_slot: 0,
+                    effective_slot: 0,
+                    reason: "builtin program".to_string(),
+                }],
+                duplicate_account_policy: proto::DuplicateAccountPolicy::Reject as i32,
+                accounts_resize_delta: 0,
+                account_resizes: vec![],
+                profile: None,
+                input_region: None,
             })
         );
     }
+
+    #[test]
+    fn test_dedup_accounts() {
+        let accounts = vec![
+            (Pubkey::new_from_array([1u8; 32]), 1u64),
+            (Pubkey::new_from_array([2u8; 32]), 2u64),
+            (Pubkey::new_from_array([1u8; 32]), 3u64),
+        ];
+
+        assert!(dedup_accounts(accounts.clone(), DuplicateAccountPolicy::Reject).is_err());
+        assert_eq!(
+            dedup_accounts(accounts.clone(), DuplicateAccountPolicy::FirstWins).unwrap(),
+            vec![accounts[0], accounts[1]]
+        );
+        assert_eq!(
+            dedup_accounts(accounts.clone(), DuplicateAccountPolicy::LastWins).unwrap(),
+            vec![(Pubkey::new_from_array([1u8; 32]), 3u64), accounts[1]]
+        );
+        assert_eq!(
+            resolve_duplicate_account_policy(DuplicateAccountPolicy::Unspecified, true),
+            DuplicateAccountPolicy::FirstWins
+        );
+    }
 }
@@ -0,0 +1,506 @@
+use crate::{
+    create_program_runtime_environments, dedup_accounts, get_instr_account_metas,
+    get_instr_accounts, is_rent_in_bounds, load_bpf_programs, load_builtins,
+    proto::{self, DuplicateAccountPolicy},
+    resolve_duplicate_account_policy, setup_sysvar_cache,
+    utils::{err_map::instr_err_to_num, loaded_programs::LoadedProgramInfo},
+    Error,
+};
+use prost::Message;
+use solana_compute_budget::compute_budget::ComputeBudget;
+use solana_log_collector::LogCollector;
+use solana_program_runtime::{
+    invoke_context::{EnvironmentConfig, InvokeContext},
+    loaded_programs::ProgramCacheForTxBatch,
+};
+use solana_sdk::{
+    account::{Account, AccountSharedData, ReadableAccount},
+    clock::Slot,
+    feature_set::FeatureSet,
+    instruction::{CompiledInstruction, InstructionError},
+    precompiles::{is_precompile, verify_if_precompile},
+    pubkey::Pubkey,
+    stable_layout::stable_instruction::StableInstruction,
+    transaction_context::{TransactionAccount, TransactionContext},
+};
+use solana_svm::transaction_processing_callback::TransactionProcessingCallback;
+use solana_timings::ExecuteTimings;
+use std::{ffi::c_int, sync::Arc};
+
+/// A mini-transaction is an ordered list of instructions executed on top of
+/// a single `InvokeContext`, sharing one `TransactionContext` and compute
+/// meter. Unlike the transaction harness, there is no `Bank`: no fees, no
+/// sanitization and no account loading checks.
+pub struct MiniTxnContext {
+    pub feature_set: FeatureSet,
+    pub accounts: Vec<(Pubkey, Account)>,
+    pub instructions: Vec<StableInstruction>,
+    pub cu_avail: u64,
+    pub slot: Option<Slot>,
+    pub duplicate_account_policy: DuplicateAccountPolicy,
+}
+
+impl TransactionProcessingCallback for MiniTxnContext {
+    fn account_matches_owners(&self, account: &Pubkey, owners: &[Pubkey]) -> Option<usize> {
+        self.accounts
+            .iter()
+            .find(|(pubkey, _)| pubkey == account)
+            .filter(|(_, account)| account.lamports != 0)
+            .and_then(|(_, account)| owners.iter().position(|entry| account.owner == *entry))
+    }
+
+    fn get_account_shared_data(&self, pubkey: &Pubkey) -> Option<AccountSharedData> {
+        self.accounts
+            .iter()
+            .find(|(key, _)| key == pubkey)
+            .map(|(_, account)| AccountSharedData::from(account.clone()))
+    }
+}
+
+impl TryFrom<proto::MiniTxnContext> for MiniTxnContext {
+    type Error = Error;
+
+    fn try_from(input: proto::MiniTxnContext) -> Result<Self, Self::Error> {
+        let feature_set: FeatureSet = input
+            .epoch_context
+            .as_ref()
+            .and_then(|epoch_ctx| epoch_ctx.features.as_ref())
+            .map(|fs| fs.into())
+            .unwrap_or_default();
+
+        let duplicate_account_policy =
+            resolve_duplicate_account_policy(input.duplicate_account_policy(), false);
+
+        let accounts: Vec<(Pubkey, Account)> = input
+            .accounts
+            .into_iter()
+            .map(|acct_state| acct_state.try_into())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let instructions = input
+            .instructions
+            .into_iter()
+            .map(|instr| {
+                let program_id = Pubkey::new_from_array(
+                    instr
+                        .program_id
+                        .try_into()
+                        .map_err(|_| Error::InvalidPubkeyBytes)?,
+                );
+                let instruction_accounts =
+                    get_instr_account_metas(&accounts, instr.instr_accounts)?;
+                Ok(StableInstruction {
+                    accounts: instruction_accounts.into(),
+                    data: instr.data.into(),
+                    program_id,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let accounts = dedup_accounts(accounts, duplicate_account_policy)?;
+
+        Ok(Self {
+            feature_set,
+            accounts,
+            instructions,
+            cu_avail: input.cu_avail,
+            slot: input.slot_context.map(|slot_context| slot_context.slot),
+            duplicate_account_policy,
+        })
+    }
+}
+
+pub struct MiniTxnInstrResult {
+    pub result: Option<InstructionError>,
+    pub custom_err: Option<u32>,
+    pub cu_consumed: u64,
+    pub return_data: Vec<u8>,
+}
+
+pub struct MiniTxnEffects {
+    /// One entry per executed instruction. Execution stops at the first
+    /// failing instruction, so later instructions have no entry.
+    pub instr_results: Vec<MiniTxnInstrResult>,
+    pub modified_accounts: Vec<(Pubkey, Account)>,
+    pub cu_avail: u64,
+    pub loaded_programs: Vec<LoadedProgramInfo>,
+    pub duplicate_account_policy: DuplicateAccountPolicy,
+}
+
+impl From<MiniTxnEffects> for proto::MiniTxnEffects {
+    fn from(val: MiniTxnEffects) -> Self {
+        proto::MiniTxnEffects {
+            instr_results: val
+                .instr_results
+                .into_iter()
+                .map(|instr_result| proto::MiniTxnInstrResult {
+                    result: instr_result
+                        .result
+                        .as_ref()
+                        .map(instr_err_to_num)
+                        .unwrap_or_default(),
+                    custom_err: instr_result.custom_err.unwrap_or_default(),
+                    cu_consumed: instr_result.cu_consumed,
+                    return_data: instr_result.return_data,
+                })
+                .collect(),
+            modified_accounts: val
+                .modified_accounts
+                .into_iter()
+                .map(|(pubkey, account)| proto::AcctState {
+                    address: pubkey.to_bytes().to_vec(),
+                    owner: account.owner.to_bytes().to_vec(),
+                    lamports: account.lamports,
+                    data: account.data.to_vec(),
+                    executable: account.executable,
+                    rent_epoch: account.rent_epoch,
+                    seed_addr: None,
+                })
+                .collect(),
+            cu_avail: val.cu_avail,
+            loaded_programs: val.loaded_programs.into_iter().map(Into::into).collect(),
+            duplicate_account_policy: val.duplicate_account_policy as i32,
+        }
+    }
+}
+
+pub fn execute_mini_txn_proto(input: proto::MiniTxnContext) -> Option<proto::MiniTxnEffects> {
+    let mini_txn_context = match MiniTxnContext::try_from(input) {
+        Ok(context) => context,
+        Err(_) => return None,
+    };
+    let mini_txn_effects = execute_mini_txn(mini_txn_context);
+    mini_txn_effects.map(Into::into)
+}
+
+fn execute_mini_txn(input: MiniTxnContext) -> Option<MiniTxnEffects> {
+    if input.instructions.is_empty() {
+        return None;
+    }
+
+    let compute_budget = ComputeBudget {
+        compute_unit_limit: input.cu_avail,
+        ..ComputeBudget::default()
+    };
+
+    let sysvar_cache = setup_sysvar_cache(&input.accounts, input.slot);
+    let clock = sysvar_cache.get_clock().unwrap();
+    let rent = (*sysvar_cache.get_rent().unwrap()).clone();
+    if !is_rent_in_bounds(&rent) {
+        return None;
+    }
+
+    let transaction_accounts: Vec<TransactionAccount> = input
+        .accounts
+        .iter()
+        .map(|(pubkey, account)| (*pubkey, AccountSharedData::from(account.clone())))
+        .collect();
+
+    let mut program_cache_for_tx_batch = ProgramCacheForTxBatch::default();
+    program_cache_for_tx_batch.set_slot_for_tests(clock.slot);
+    let loaded_builtins = load_builtins(&mut program_cache_for_tx_batch);
+
+    // Resolve every program up front, so that inputs the instruction harness
+    // would reject are rejected before anything is executed
+    let mut program_indices = Vec::with_capacity(input.instructions.len());
+    for instruction in &input.instructions {
+        let program_idx = transaction_accounts
+            .iter()
+            .position(|(pubkey, _)| *pubkey == instruction.program_id)?;
+
+        // Skip if the program account is a native program and is not owned by the native loader
+        // (Would call the owner instead)
+        if loaded_builtins.contains(&transaction_accounts[program_idx].0)
+            && transaction_accounts[program_idx].1.owner() != &solana_sdk::native_loader::id()
+        {
+            return None;
+        }
+        program_indices.push(program_idx as u16);
+    }
+
+    let environments = create_program_runtime_environments(&input.feature_set, &compute_budget);
+    program_cache_for_tx_batch.environments = environments.clone();
+    program_cache_for_tx_batch.upcoming_environments = Some(environments.clone());
+
+    let loaded_programs = load_bpf_programs(
+        &input,
+        &input.accounts,
+        &environments,
+        clock.slot,
+        &mut program_cache_for_tx_batch,
+    );
+
+    #[allow(deprecated)]
+    let (blockhash, lamports_per_signature) = sysvar_cache
+        .get_recent_blockhashes()
+        .ok()
+        .and_then(|x| (*x).last().cloned())
+        .map(|x| (x.blockhash, x.fee_calculator.lamports_per_signature))
+        .unwrap_or_default();
+
+    let mut transaction_context = TransactionContext::new(
+        transaction_accounts.clone(),
+        rent,
+        compute_budget.max_instruction_stack_depth,
+        compute_budget.max_instruction_trace_length,
+    );
+
+    let log_collector = LogCollector::new_ref();
+    let env_config = EnvironmentConfig::new(
+        blockhash,
+        None,
+        None,
+        Arc::new(input.feature_set.clone()),
+        lamports_per_signature,
+        &sysvar_cache,
+    );
+    let mut invoke_context = InvokeContext::new(
+        &mut transaction_context,
+        &mut program_cache_for_tx_batch,
+        env_config,
+        Some(log_collector.clone()),
+        compute_budget,
+    );
+
+    // Precompiles may read the data of any instruction in the transaction, so
+    // they are verified against the whole instruction list
+    let compiled_instructions: Vec<CompiledInstruction> = input
+        .instructions
+        .iter()
+        .map(|instruction| CompiledInstruction {
+            program_id_index: 0,
+            accounts: vec![],
+            data: instruction.data.to_vec(),
+        })
+        .collect();
+
+    let mut timings = ExecuteTimings::default();
+    let mut instr_results = Vec::with_capacity(input.instructions.len());
+    let mut total_cu_consumed = 0u64;
+
+    for (index, instruction) in input.instructions.iter().enumerate() {
+        let program_id = &instruction.program_id;
+        let is_precompile = is_precompile(program_id, |id| {
+            invoke_context.environment_config.feature_set.is_active(id)
+        });
+
+        // Like in a transaction, precompiles are verified instead of executed
+        // and do not consume compute units. They still get an entry in the
+        // instruction trace, and verification errors are reported as custom
+        // errors carrying the precompile error code
+        let mut compute_units_consumed = 0u64;
+        let instruction_accounts = get_instr_accounts(&transaction_accounts, &instruction.accounts);
+        let result = if is_precompile {
+            invoke_context
+                .transaction_context
+                .get_next_instruction_context()
+                .map(|instruction_context| {
+                    instruction_context.configure(
+                        &program_indices[index..index + 1],
+                        &instruction_accounts,
+                        &instruction.data,
+                    );
+                })
+                .and_then(|_| invoke_context.transaction_context.push())
+                .and_then(|_| {
+                    verify_if_precompile(
+                        program_id,
+                        &compiled_instructions[index],
+                        &compiled_instructions,
+                        &invoke_context.environment_config.feature_set,
+                    )
+                    .map_err(|error| InstructionError::Custom(error as u32))
+                    // The instruction is popped whether or not verification
+                    // succeeded, like the message processor does
+                    .and(invoke_context.transaction_context.pop())
+                })
+        } else {
+            invoke_context.process_instruction(
+                &instruction.data,
+                &instruction_accounts,
+                &program_indices[index..index + 1],
+                &mut compute_units_consumed,
+                &mut timings,
+            )
+        };
+        total_cu_consumed = total_cu_consumed.saturating_add(compute_units_consumed);
+
+        instr_results.push(MiniTxnInstrResult {
+            custom_err: if let Err(InstructionError::Custom(x)) = result {
+                Some(x)
+            } else {
+                None
+            },
+            result: result.clone().err(),
+            cu_consumed: compute_units_consumed,
+            return_data: invoke_context
+                .transaction_context
+                .get_return_data()
+                .1
+                .to_vec(),
+        });
+
+        if result.is_err() {
+            break;
+        }
+    }
+
+    Some(MiniTxnEffects {
+        instr_results,
+        modified_accounts: transaction_context
+            .deconstruct_without_keys()
+            .unwrap()
+            .into_iter()
+            .enumerate()
+            .map(|(index, data)| (transaction_accounts[index].0, data.into()))
+            .collect(),
+        cu_avail: input.cu_avail.saturating_sub(total_cu_consumed),
+        loaded_programs,
+        duplicate_account_policy: input.duplicate_account_policy,
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sol_compat_mini_txn_execute_v1(
+    out_ptr: *mut u8,
+    out_psz: *mut u64,
+    in_ptr: *mut u8,
+    in_sz: u64,
+) -> c_int {
+    let in_slice = std::slice::from_raw_parts(in_ptr, in_sz as usize);
+    let mini_txn_context = match proto::MiniTxnContext::decode(in_slice) {
+        Ok(context) => context,
+        Err(_) => return 0,
+    };
+    let mini_txn_effects = match execute_mini_txn_proto(mini_txn_context) {
+        Some(v) => v,
+        None => return 0,
+    };
+    let out_slice = std::slice::from_raw_parts_mut(out_ptr, (*out_psz) as usize);
+    let out_vec = mini_txn_effects.encode_to_vec();
+    if out_vec.len() > out_slice.len() {
+        return 0;
+    }
+    out_slice[..out_vec.len()].copy_from_slice(&out_vec);
+    *out_psz = out_vec.len() as u64;
+
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::system_account;
+    use solana_sdk::precompiles::PrecompileError;
+
+    fn transfer(from: u32, to: u32, lamports: u8) -> proto::MiniTxnInstr {
+        proto::MiniTxnInstr {
+            program_id: vec![0u8; 32],
+            instr_accounts: vec![
+                proto::InstrAcct {
+                    index: from,
+                    is_signer: true,
+                    is_writable: true,
+                },
+                proto::InstrAcct {
+                    index: to,
+                    is_signer: false,
+                    is_writable: true,
+                },
+            ],
+            data: vec![
+                // Transfer
+                0x02, 0x00, 0x00, 0x00, // Lamports
+                lamports, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ],
+        }
+    }
+
+    #[test]
+    fn test_mini_txn_system_transfers() {
+        let native_loader_id = solana_sdk::native_loader::id().to_bytes().to_vec();
+        let system_program = proto::AcctState {
+            address: vec![0u8; 32],
+            owner: native_loader_id,
+            lamports: 10000000,
+            data: b"Solana Program".to_vec(),
+            executable: true,
+            rent_epoch: 0,
+            seed_addr: None,
+        };
+
+        // The second transfer spends lamports received in the first one, and
+        // the third one fails, so it is the last instruction executed
+        let input = proto::MiniTxnContext {
+            accounts: vec![
+                system_account(&Pubkey::new_from_array([1; 32]), 1000),
+                system_account(&Pubkey::new_from_array([2; 32]), 0),
+                system_account(&Pubkey::new_from_array([3; 32]), 0),
+                system_program,
+            ],
+            instructions: vec![
+                transfer(0, 1, 10),
+                transfer(1, 2, 4),
+                transfer(2, 0, 5),
+                transfer(0, 1, 1),
+            ],
+            cu_avail: 10000u64,
+            epoch_context: None,
+            slot_context: None,
+            duplicate_account_policy: 0,
+        };
+        let output = execute_mini_txn_proto(input).unwrap();
+
+        assert_eq!(output.instr_results.len(), 3);
+        assert_eq!(output.instr_results[0].result, 0);
+        assert_eq!(output.instr_results[0].cu_consumed, 150);
+        assert_eq!(output.instr_results[1].result, 0);
+        assert_eq!(output.instr_results[1].cu_consumed, 150);
+        assert_ne!(output.instr_results[2].result, 0);
+        assert_eq!(output.cu_avail, 10000 - 450);
+
+        let lamports: Vec<u64> = output
+            .modified_accounts
+            .iter()
+            .map(|account| account.lamports)
+            .collect();
+        assert_eq!(lamports, vec![990, 6, 4, 10000000]);
+    }
+
+    #[test]
+    fn test_mini_txn_precompile_error() {
+        let ed25519_program = proto::AcctState {
+            address: solana_sdk::ed25519_program::id().to_bytes().to_vec(),
+            owner: solana_sdk::native_loader::id().to_bytes().to_vec(),
+            lamports: 1,
+            data: vec![],
+            executable: true,
+            rent_epoch: 0,
+            seed_addr: None,
+        };
+        let input = proto::MiniTxnContext {
+            accounts: vec![ed25519_program],
+            instructions: vec![proto::MiniTxnInstr {
+                program_id: solana_sdk::ed25519_program::id().to_bytes().to_vec(),
+                instr_accounts: vec![],
+                data: vec![],
+            }],
+            cu_avail: 10000u64,
+            epoch_context: None,
+            slot_context: None,
+            duplicate_account_policy: 0,
+        };
+        // A failed verification still returns the effects of the transaction
+        let output = execute_mini_txn_proto(input).unwrap();
+        assert_eq!(output.modified_accounts.len(), 1);
+
+        let error_code = PrecompileError::InvalidInstructionDataSize as u32;
+        assert_eq!(output.instr_results.len(), 1);
+        assert_eq!(
+            output.instr_results[0].result,
+            instr_err_to_num(&InstructionError::Custom(error_code))
+        );
+        assert_eq!(output.instr_results[0].custom_err, error_code);
+        assert_eq!(output.instr_results[0].cu_consumed, 0);
+        assert_eq!(output.cu_avail, 10000);
+    }
+}
@@ -1,45 +1,71 @@
 use crate::proto::{self, ResultingState};
 use crate::proto::{AcctState, TransactionMessage, TxnContext, TxnResult};
+use crate::utils::entrypoints::{self, observe_entrypoint};
+use crate::utils::err_map::{
+    instr_err_to_num, txn_err_account_index, txn_err_instruction_index, txn_err_to_num,
+};
+use crate::utils::profile;
+use crate::{dedup_accounts, resolve_duplicate_account_policy};
+use bincode::Options;
 use prost::Message;
 use solana_accounts_db::accounts_db::{AccountShrinkThreshold, AccountsDbConfig};
 use solana_accounts_db::accounts_file::StorageAccess;
 use solana_accounts_db::accounts_index::{
     AccountSecondaryIndexes, AccountsIndexConfig, IndexLimitMb,
 };
+use solana_bpf_loader_program::syscalls::create_program_runtime_environment_v1;
+use solana_cost_model::cost_model::CostModel;
+use solana_cost_model::cost_tracker::CostTracker;
 use solana_program::hash::Hash;
 use solana_program::instruction::CompiledInstruction;
 use solana_program::message::v0::MessageAddressTableLookup;
-use solana_program::message::{legacy, v0, MessageHeader, VersionedMessage};
+use solana_program::message::{legacy, v0, MessageHeader, SanitizedMessage, VersionedMessage};
 use solana_program::pubkey::Pubkey;
+use solana_program_runtime::loaded_programs::{
+    ProgramCacheEntry, ProgramCacheEntryType, ProgramRuntimeEnvironments,
+};
 use solana_runtime::bank::builtins::BUILTINS;
 use solana_runtime::bank::{Bank, LoadAndExecuteTransactionsOutput};
 use solana_runtime::bank_forks::BankForks;
 use solana_sdk::account::{AccountSharedData, ReadableAccount};
+use solana_sdk::address_lookup_table::{
+    self,
+    state::{AddressLookupTable, LookupTableStatus},
+};
+use solana_sdk::clock::Slot;
 use solana_sdk::epoch_schedule::EpochSchedule;
-use solana_sdk::feature_set::FeatureSet;
+use solana_sdk::feature_set::{reward_full_priority_fee, FeatureSet};
+use solana_sdk::fee::{FeeBin, FeeStructure};
+use solana_sdk::fee_calculator::FeeRateGovernor;
 use solana_sdk::genesis_config::GenesisConfig;
 use solana_sdk::instruction::InstructionError;
-use solana_sdk::message::SanitizedMessage;
+use solana_sdk::native_loader;
+use solana_sdk::packet::PACKET_DATA_SIZE;
 use solana_sdk::rent::Rent;
 use solana_sdk::signature::Signature;
 use solana_sdk::sysvar;
 use solana_sdk::transaction::{
-    TransactionError, TransactionVerificationMode, VersionedTransaction,
+    SanitizedTransaction, TransactionError, TransactionVerificationMode, VersionedTransaction,
 };
 use solana_sdk::transaction_context::TransactionAccount;
 use solana_svm::account_loader::LoadedTransaction;
+use solana_svm::rollback_accounts::RollbackAccounts;
 use solana_svm::runtime_config::RuntimeConfig;
 use solana_svm::transaction_error_metrics::TransactionErrorMetrics;
 use solana_svm::transaction_processing_result::{
-    ProcessedTransaction, TransactionProcessingResultExtensions,
+    ProcessedTransaction, TransactionProcessingResult, TransactionProcessingResultExtensions,
 };
 use solana_svm::transaction_processor::{ExecutionRecordingConfig, TransactionProcessingConfig};
 use solana_timings::ExecuteTimings;
+use std::cell::{Cell, RefCell};
 use std::cmp::max;
 use std::collections::HashSet;
 use std::ffi::c_int;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::AtomicBool;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
+use tempfile::TempDir;
+use thiserror::Error;
 
 #[no_mangle]
 pub unsafe extern "C" fn sol_compat_txn_execute_v1(
@@ -74,47 +100,119 @@ pub unsafe extern "C" fn sol_compat_txn_execute_v1(
     1
 }
 
-impl From<&proto::MessageHeader> for MessageHeader {
-    fn from(value: &proto::MessageHeader) -> Self {
-        MessageHeader {
-            num_required_signatures: max(1, value.num_required_signatures as u8),
-            num_readonly_signed_accounts: value.num_readonly_signed_accounts as u8,
-            num_readonly_unsigned_accounts: value.num_readonly_unsigned_accounts as u8,
+/// Compute fee bins default to Agave's when none are given.
+impl From<&proto::FeeStructure> for FeeStructure {
+    fn from(value: &proto::FeeStructure) -> Self {
+        let compute_fee_bins = if value.compute_fee_bins.is_empty() {
+            FeeStructure::default().compute_fee_bins
+        } else {
+            value
+                .compute_fee_bins
+                .iter()
+                .map(|bin| FeeBin {
+                    limit: bin.limit,
+                    fee: bin.fee,
+                })
+                .collect()
+        };
+        FeeStructure {
+            lamports_per_signature: value.lamports_per_signature,
+            lamports_per_write_lock: value.lamports_per_write_lock,
+            compute_fee_bins,
         }
     }
 }
 
-impl From<&proto::CompiledInstruction> for CompiledInstruction {
-    fn from(value: &proto::CompiledInstruction) -> Self {
-        CompiledInstruction {
-            program_id_index: value.program_id_index as u8,
-            accounts: value.accounts.iter().map(|idx| *idx as u8).collect(),
+/// Malformed transaction message fields, which would make Firedancer's
+/// transaction parser reject the transaction. Discriminants are reported as
+/// the message build error of the transaction result, 0 meaning none.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum MessageBuildError {
+    #[error("Missing transaction message")]
+    MissingMessage = 1,
+
+    #[error("Invalid account key length")]
+    InvalidAccountKey = 2,
+
+    #[error("Invalid address lookup table key length")]
+    InvalidLookupTableKey = 3,
+
+    #[error("Invalid recent blockhash length")]
+    InvalidRecentBlockhash = 4,
+
+    #[error("Invalid signature length")]
+    InvalidSignature = 5,
+
+    #[error("Index out of range")]
+    IndexOutOfRange = 6,
+
+    #[error("Header count out of range")]
+    HeaderCountOutOfRange = 7,
+}
+
+fn to_index(index: &u32) -> Result<u8, MessageBuildError> {
+    u8::try_from(*index).map_err(|_| MessageBuildError::IndexOutOfRange)
+}
+
+fn to_header_count(count: u32) -> Result<u8, MessageBuildError> {
+    u8::try_from(count).map_err(|_| MessageBuildError::HeaderCountOutOfRange)
+}
+
+impl TryFrom<&proto::MessageHeader> for MessageHeader {
+    type Error = MessageBuildError;
+
+    fn try_from(value: &proto::MessageHeader) -> Result<Self, Self::Error> {
+        Ok(MessageHeader {
+            num_required_signatures: max(1, to_header_count(value.num_required_signatures)?),
+            num_readonly_signed_accounts: to_header_count(value.num_readonly_signed_accounts)?,
+            num_readonly_unsigned_accounts: to_header_count(value.num_readonly_unsigned_accounts)?,
+        })
+    }
+}
+
+impl TryFrom<&proto::CompiledInstruction> for CompiledInstruction {
+    type Error = MessageBuildError;
+
+    fn try_from(value: &proto::CompiledInstruction) -> Result<Self, Self::Error> {
+        Ok(CompiledInstruction {
+            program_id_index: to_index(&value.program_id_index)?,
+            accounts: value
+                .accounts
+                .iter()
+                .map(to_index)
+                .collect::<Result<_, _>>()?,
             data: value.data.clone(),
-        }
+        })
     }
 }
 
-impl From<&proto::MessageAddressTableLookup> for MessageAddressTableLookup {
-    fn from(value: &proto::MessageAddressTableLookup) -> Self {
-        MessageAddressTableLookup {
-            account_key: Pubkey::new_from_array(value.account_key.clone().try_into().unwrap()),
+impl TryFrom<&proto::MessageAddressTableLookup> for MessageAddressTableLookup {
+    type Error = MessageBuildError;
+
+    fn try_from(value: &proto::MessageAddressTableLookup) -> Result<Self, Self::Error> {
+        Ok(MessageAddressTableLookup {
+            account_key: Pubkey::try_from(value.account_key.as_slice())
+                .map_err(|_| MessageBuildError::InvalidLookupTableKey)?,
             writable_indexes: value
                 .writable_indexes
                 .iter()
-                .map(|idx| *idx as u8)
-                .collect(),
+                .map(to_index)
+                .collect::<Result<_, _>>()?,
             readonly_indexes: value
                 .readonly_indexes
                 .iter()
-                .map(|idx| *idx as u8)
-                .collect(),
-        }
+                .map(to_index)
+                .collect::<Result<_, _>>()?,
+        })
     }
 }
 
-fn build_versioned_message(value: &TransactionMessage) -> Option<VersionedMessage> {
+fn build_versioned_message(
+    value: &TransactionMessage,
+) -> Result<VersionedMessage, MessageBuildError> {
     let header = if let Some(value_header) = value.header {
-        MessageHeader::from(&value_header)
+        MessageHeader::try_from(&value_header)?
     } else {
         // Default: valid txn header with 1 signature (this keeps tests simpler)
         MessageHeader {
@@ -126,19 +224,27 @@ fn build_versioned_message(value: &TransactionMessage) -> Option<VersionedMessag
     let account_keys = value
         .account_keys
         .iter()
-        .map(|key| Pubkey::new_from_array(key.clone().try_into().unwrap()))
-        .collect::<Vec<Pubkey>>();
+        .map(|key| {
+            Pubkey::try_from(key.as_slice()).map_err(|_| MessageBuildError::InvalidAccountKey)
+        })
+        .collect::<Result<Vec<Pubkey>, _>>()?;
     let recent_blockhash = if value.recent_blockhash.is_empty() {
         // Default: empty blockchash (this keeps tests simpler)
         Hash::new_from_array([0u8; 32])
     } else {
-        Hash::new(&value.recent_blockhash)
+        Hash::new_from_array(
+            value
+                .recent_blockhash
+                .as_slice()
+                .try_into()
+                .map_err(|_| MessageBuildError::InvalidRecentBlockhash)?,
+        )
     };
     let instructions = value
         .instructions
         .iter()
-        .map(CompiledInstruction::from)
-        .collect::<Vec<CompiledInstruction>>();
+        .map(CompiledInstruction::try_from)
+        .collect::<Result<Vec<CompiledInstruction>, _>>()?;
 
     if value.is_legacy {
         let message = legacy::Message {
@@ -147,13 +253,13 @@ fn build_versioned_message(value: &TransactionMessage) -> Option<VersionedMessag
             recent_blockhash,
             instructions,
         };
-        Some(VersionedMessage::Legacy(message))
+        Ok(VersionedMessage::Legacy(message))
     } else {
         let address_table_lookups = value
             .address_table_lookups
             .iter()
-            .map(MessageAddressTableLookup::from)
-            .collect::<Vec<MessageAddressTableLookup>>();
+            .map(MessageAddressTableLookup::try_from)
+            .collect::<Result<Vec<MessageAddressTableLookup>, _>>()?;
 
         let message = v0::Message {
             header,
@@ -163,7 +269,74 @@ fn build_versioned_message(value: &TransactionMessage) -> Option<VersionedMessag
             address_table_lookups,
         };
 
-        Some(VersionedMessage::V0(message))
+        Ok(VersionedMessage::V0(message))
+    }
+}
+
+impl From<&MessageHeader> for proto::MessageHeader {
+    fn from(value: &MessageHeader) -> Self {
+        proto::MessageHeader {
+            num_required_signatures: value.num_required_signatures.into(),
+            num_readonly_signed_accounts: value.num_readonly_signed_accounts.into(),
+            num_readonly_unsigned_accounts: value.num_readonly_unsigned_accounts.into(),
+        }
+    }
+}
+
+impl From<&CompiledInstruction> for proto::CompiledInstruction {
+    fn from(value: &CompiledInstruction) -> Self {
+        proto::CompiledInstruction {
+            program_id_index: value.program_id_index.into(),
+            accounts: value.accounts.iter().map(|idx| (*idx).into()).collect(),
+            data: value.data.clone(),
+        }
+    }
+}
+
+impl From<&MessageAddressTableLookup> for proto::MessageAddressTableLookup {
+    fn from(value: &MessageAddressTableLookup) -> Self {
+        proto::MessageAddressTableLookup {
+            account_key: value.account_key.to_bytes().to_vec(),
+            writable_indexes: value
+                .writable_indexes
+                .iter()
+                .map(|idx| (*idx).into())
+                .collect(),
+            readonly_indexes: value
+                .readonly_indexes
+                .iter()
+                .map(|idx| (*idx).into())
+                .collect(),
+        }
+    }
+}
+
+/// Reverse of `build_versioned_message`. Account states are not part of a
+/// message and are left empty.
+impl From<&VersionedMessage> for TransactionMessage {
+    fn from(value: &VersionedMessage) -> Self {
+        TransactionMessage {
+            is_legacy: matches!(value, VersionedMessage::Legacy(_)),
+            header: Some(value.header().into()),
+            account_keys: value
+                .static_account_keys()
+                .iter()
+                .map(|key| key.to_bytes().to_vec())
+                .collect(),
+            account_shared_data: vec![],
+            instructions: value
+                .instructions()
+                .iter()
+                .map(proto::CompiledInstruction::from)
+                .collect(),
+            address_table_lookups: value
+                .address_table_lookups()
+                .unwrap_or_default()
+                .iter()
+                .map(proto::MessageAddressTableLookup::from)
+                .collect(),
+            recent_blockhash: value.recent_blockhash().to_bytes().to_vec(),
+        }
     }
 }
 
@@ -202,20 +375,24 @@ impl From<LoadedTransaction> for proto::ResultingState {
             acct_states,
             rent_debits,
             transaction_rent: value.rent,
+            // Filled in once the resulting state is pruned
+            acct_provenance: vec![],
         }
     }
 }
 
 impl From<LoadAndExecuteTransactionsOutput> for TxnResult {
     fn from(value: LoadAndExecuteTransactionsOutput) -> TxnResult {
-        let execution_results = &value.processing_results[0];
+        (&value.processing_results[0]).into()
+    }
+}
+
+impl From<&TransactionProcessingResult> for TxnResult {
+    fn from(execution_results: &TransactionProcessingResult) -> TxnResult {
         let (
             is_ok,
             sanitization_error,
-            status,
-            instruction_error,
-            instruction_error_index,
-            custom_error,
+            transaction_error,
             executed_units,
             return_data,
             fee_details,
@@ -229,34 +406,6 @@ impl From<LoadAndExecuteTransactionsOutput> for TxnResult {
                     }
                     ProcessedTransaction::FeesOnly(_) => false,
                 };
-                let transaction_error = txn.status();
-                let (instr_err_idx, instruction_error) = match transaction_error.as_ref() {
-                    Err(TransactionError::InstructionError(instr_err_idx, instr_err)) => {
-                        (*instr_err_idx, Some(instr_err.clone()))
-                    }
-                    _ => (0, None),
-                };
-                let custom_error = match instruction_error {
-                    Some(InstructionError::Custom(custom_error)) => custom_error,
-                    _ => 0,
-                };
-
-                let status = match transaction_error {
-                    Ok(_) => 0,
-                    Err(transaction_error) => {
-                        let serialized =
-                            bincode::serialize(&transaction_error).unwrap_or(vec![0, 0, 0, 0]);
-                        u32::from_le_bytes(serialized[0..4].try_into().unwrap()) + 1
-                    }
-                };
-                let instr_err_no = match instruction_error {
-                    Some(instruction_error) => {
-                        let serialized =
-                            bincode::serialize(&instruction_error).unwrap_or(vec![0, 0, 0, 0]);
-                        u32::from_le_bytes(serialized[0..4].try_into().unwrap()) + 1
-                    }
-                    None => 0,
-                };
                 let rent = match txn {
                     ProcessedTransaction::Executed(executed_tx) => {
                         executed_tx.loaded_transaction.rent
@@ -287,10 +436,7 @@ impl From<LoadAndExecuteTransactionsOutput> for TxnResult {
                 (
                     is_ok,
                     false,
-                    status,
-                    instr_err_no,
-                    instr_err_idx,
-                    custom_error,
+                    txn.status().err(),
                     executed_units,
                     return_data,
                     Some(txn.fee_details()),
@@ -298,112 +444,575 @@ impl From<LoadAndExecuteTransactionsOutput> for TxnResult {
                     resulting_state,
                 )
             }
-            Err(transaction_error) => {
-                let (instr_err_idx, instruction_error) = match transaction_error {
-                    TransactionError::InstructionError(instr_err_idx, instr_err) => {
-                        (*instr_err_idx, Some(instr_err.clone()))
-                    }
-                    _ => (0, None),
-                };
-                let instr_err_no = match instruction_error {
-                    Some(instruction_error) => {
-                        let serialized =
-                            bincode::serialize(&instruction_error).unwrap_or(vec![0, 0, 0, 0]);
-                        u32::from_le_bytes(serialized[0..4].try_into().unwrap()) + 1
-                    }
-                    None => 0,
-                };
-                let serialized = bincode::serialize(transaction_error).unwrap_or(vec![0, 0, 0, 0]);
-                let status = u32::from_le_bytes(serialized[0..4].try_into().unwrap()) + 1;
-                (
-                    false,
-                    true,
-                    status,
-                    instr_err_no,
-                    instr_err_idx,
-                    0,
-                    0,
-                    vec![],
-                    None,
-                    0,
-                    None,
-                )
+            Err(transaction_error) => (
+                false,
+                true,
+                Some(transaction_error.clone()),
+                0,
+                vec![],
+                None,
+                0,
+                None,
+            ),
+        };
+        let error_fields = TransactionErrorFields::from(transaction_error.as_ref());
+
+        let accounts_resize_delta = match execution_results {
+            Ok(ProcessedTransaction::Executed(executed_tx)) => {
+                executed_tx.execution_details.accounts_data_len_delta
             }
+            _ => 0,
         };
 
+        let (log_messages, inner_instructions, loaded_accounts_data_size, return_data_program_id) =
+            match execution_results {
+                Ok(ProcessedTransaction::Executed(executed_tx)) => {
+                    let execution_details = &executed_tx.execution_details;
+                    (
+                        execution_details.log_messages.clone().unwrap_or_default(),
+                        execution_details
+                            .inner_instructions
+                            .as_ref()
+                            .map(|inner_instructions| {
+                                inner_instructions
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(index, instructions)| proto::InnerInstructions {
+                                        index: index as u32,
+                                        instructions: instructions
+                                            .iter()
+                                            .map(|inner| proto::InnerInstruction {
+                                                program_id_index: inner.instruction.program_id_index
+                                                    as u32,
+                                                accounts: inner.instruction.accounts.clone(),
+                                                data: inner.instruction.data.clone(),
+                                                stack_height: inner.stack_height as u32,
+                                            })
+                                            .collect(),
+                                    })
+                                    .collect()
+                            })
+                            .unwrap_or_default(),
+                        executed_tx.loaded_transaction.loaded_accounts_data_size as u64,
+                        execution_details
+                            .return_data
+                            .as_ref()
+                            .map(|info| info.program_id.to_bytes().to_vec())
+                            .unwrap_or_default(),
+                    )
+                }
+                _ => (vec![], vec![], 0, vec![]),
+            };
+
         TxnResult {
             executed: execution_results.was_processed(),
             sanitization_error,
+            deserialization_error: false,
+            message_build_error: 0,
+            signature_failure: false,
+            // Filled in by the harness
+            epoch_sysvars: vec![],
+            fee_distribution: None,
+            cost: None,
+            loaded_addresses: None,
+            alt_error: 0,
+            alt_error_lookup_index: 0,
+            is_nonce_transaction: false,
+            nonce_account: None,
+            rollback_accounts: vec![],
             resulting_state,
             rent,
             is_ok,
-            status,
-            instruction_error,
-            instruction_error_index: instruction_error_index.into(),
-            custom_error,
+            status: error_fields.status,
+            instruction_error: error_fields.instruction_error,
+            instruction_error_index: error_fields.instruction_error_index,
+            custom_error: error_fields.custom_error,
+            error_account_index: error_fields.error_account_index,
             return_data,
             executed_units,
             fee_details: fee_details.map(|fees| proto::FeeDetails {
                 transaction_fee: fees.transaction_fee(),
                 prioritization_fee: fees.prioritization_fee(),
             }),
+            accounts_resize_delta,
+            log_messages,
+            inner_instructions,
+            loaded_accounts_data_size,
+            return_data_program_id,
+            // Filled in by the harness
+            duplicate_account_policy: 0,
+            account_resizes: vec![],
+            profile: None,
         }
     }
 }
 
-#[allow(deprecated)]
-pub fn execute_transaction(context: TxnContext) -> Option<TxnResult> {
-    let fd_features = context
-        .epoch_ctx
-        .as_ref()
-        .map(|ctx| ctx.features.clone().unwrap_or_default())
-        .unwrap_or_default();
-
-    let feature_set = FeatureSet::from(&fd_features);
-    let fee_collector = Pubkey::new_unique();
-    let slot = context.slot_ctx.as_ref().map(|ctx| ctx.slot).unwrap_or(10); // Arbitrary default > 0
+/// Builds the versioned transaction described by a protobuf transaction.
+pub fn build_versioned_transaction(
+    tx: &proto::SanitizedTransaction,
+) -> Result<VersionedTransaction, MessageBuildError> {
+    let message = build_versioned_message(
+        tx.message
+            .as_ref()
+            .ok_or(MessageBuildError::MissingMessage)?,
+    )?;
 
-    /* HACK: Set the genesis config rent and epoch schedule from the "to-be" sysvars, if present */
-    let rent: Rent = context
-        .tx
-        .as_ref()?
-        .message
-        .as_ref()?
-        .account_shared_data
-        .iter()
-        .find(|item| item.address.as_slice() == sysvar::rent::id().as_ref() && item.lamports > 0)
-        .map(|account| bincode::deserialize(&account.data).ok())
-        .unwrap_or_default()
-        .unwrap_or_default();
-    let epoch_schedule: EpochSchedule = context
-        .tx
-        .as_ref()?
-        .message
-        .as_ref()?
-        .account_shared_data
+    let mut signatures = tx
+        .signatures
         .iter()
-        .find(|item| {
-            item.address.as_slice() == sysvar::epoch_schedule::id().as_ref() && item.lamports > 0
+        .map(|item| {
+            Signature::try_from(item.as_slice()).map_err(|_| MessageBuildError::InvalidSignature)
         })
-        .map(|account| bincode::deserialize(&account.data).ok())
-        .unwrap_or_default()
-        .unwrap_or_default();
+        .collect::<Result<Vec<Signature>, _>>()?;
+    if signatures.is_empty() {
+        // Default: valid txn with 1 empty signature (this keeps tests simpler)
+        signatures.push(Signature::default());
+    }
 
-    let genesis_config = GenesisConfig {
-        creation_time: 0,
-        rent,
-        epoch_schedule,
-        ..GenesisConfig::default()
-    };
+    Ok(VersionedTransaction {
+        message,
+        signatures,
+    })
+}
 
-    let mut blockhash_queue = if context.blockhash_queue.is_empty() {
-        vec![vec![0u8; 32]]
-    } else {
-        context.blockhash_queue
+/// Deserializes a wire-format transaction the way Agave deserializes packets.
+/// Packets larger than `PACKET_DATA_SIZE` or with bytes past the end of the
+/// transaction are rejected.
+pub fn deserialize_raw_transaction(raw_tx: &[u8]) -> bincode::Result<VersionedTransaction> {
+    if raw_tx.len() > PACKET_DATA_SIZE {
+        return Err(Box::new(bincode::ErrorKind::SizeLimit));
+    }
+    bincode::options()
+        .with_limit(PACKET_DATA_SIZE as u64)
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(raw_tx)
+}
+
+/// Status fields of a `TxnResult` describing a transaction error. The
+/// instruction index is also set for duplicate instruction errors.
+#[derive(Default)]
+pub(crate) struct TransactionErrorFields {
+    pub(crate) status: u32,
+    pub(crate) instruction_error: u32,
+    pub(crate) instruction_error_index: u32,
+    pub(crate) custom_error: u32,
+    pub(crate) error_account_index: u32,
+}
+
+impl From<Option<&TransactionError>> for TransactionErrorFields {
+    fn from(error: Option<&TransactionError>) -> Self {
+        let Some(error) = error else {
+            return TransactionErrorFields::default();
+        };
+        let instruction_error = match error {
+            TransactionError::InstructionError(_, instruction_error) => Some(instruction_error),
+            _ => None,
+        };
+        TransactionErrorFields {
+            status: txn_err_to_num(error),
+            instruction_error: instruction_error
+                .map(|instruction_error| instr_err_to_num(instruction_error) as u32)
+                .unwrap_or_default(),
+            instruction_error_index: txn_err_instruction_index(error)
+                .map(u32::from)
+                .unwrap_or_default(),
+            custom_error: match instruction_error {
+                Some(InstructionError::Custom(custom_error)) => *custom_error,
+                _ => 0,
+            },
+            error_account_index: txn_err_account_index(error)
+                .map(u32::from)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Result of a wire-format transaction which could not be deserialized.
+fn deserialization_error_result() -> TxnResult {
+    TxnResult {
+        sanitization_error: false,
+        status: 0,
+        deserialization_error: true,
+        ..sanitization_error_result(&TransactionError::SanitizeFailure)
+    }
+}
+
+/// Result of a transaction whose message could not be built. Firedancer's
+/// parser rejects such messages, which Agave reports as a sanitize failure.
+pub(crate) fn message_build_error_result(error: MessageBuildError) -> TxnResult {
+    TxnResult {
+        message_build_error: error as u32,
+        ..sanitization_error_result(&TransactionError::SanitizeFailure)
+    }
+}
+
+/// Result of a transaction which failed verification and was not executed.
+pub(crate) fn sanitization_error_result(error: &TransactionError) -> TxnResult {
+    let error_fields = TransactionErrorFields::from(Some(error));
+    TxnResult {
+        executed: false,
+        sanitization_error: true,
+        deserialization_error: false,
+        message_build_error: 0,
+        signature_failure: *error == TransactionError::SignatureFailure,
+        epoch_sysvars: vec![],
+        fee_distribution: None,
+        cost: None,
+        loaded_addresses: None,
+        alt_error: 0,
+        alt_error_lookup_index: 0,
+        is_nonce_transaction: false,
+        nonce_account: None,
+        rollback_accounts: vec![],
+        resulting_state: None,
+        rent: 0,
+        is_ok: false,
+        status: error_fields.status,
+        instruction_error: error_fields.instruction_error,
+        instruction_error_index: error_fields.instruction_error_index,
+        custom_error: error_fields.custom_error,
+        error_account_index: error_fields.error_account_index,
+        return_data: vec![],
+        executed_units: 0,
+        fee_details: None,
+        duplicate_account_policy: 0,
+        accounts_resize_delta: 0,
+        account_resizes: vec![],
+        profile: None,
+        log_messages: vec![],
+        inner_instructions: vec![],
+        loaded_accounts_data_size: 0,
+        return_data_program_id: vec![],
+    }
+}
+
+/// Reports whether the transaction was a durable nonce transaction along
+/// with its advanced nonce account, and the accounts committed in place of
+/// the resulting state when the transaction failed. Fee-only transactions
+/// only commit these accounts, which make up their resulting state.
+pub(crate) fn set_rollback_state(
+    txn_result: &mut TxnResult,
+    processing_result: &TransactionProcessingResult,
+    sanitized_transaction: &SanitizedTransaction,
+) {
+    let rollback_accounts = match processing_result {
+        Ok(ProcessedTransaction::Executed(executed_tx)) => {
+            &executed_tx.loaded_transaction.rollback_accounts
+        }
+        Ok(ProcessedTransaction::FeesOnly(fees_only_tx)) => &fees_only_tx.rollback_accounts,
+        Err(_) => return,
     };
-    let genesis_hash = Some(Hash::new(blockhash_queue[0].as_slice()));
 
-    // Bank on slot 0
+    if let Some(nonce) = rollback_accounts.nonce() {
+        txn_result.is_nonce_transaction = true;
+        txn_result.nonce_account = Some((*nonce.address(), nonce.account().clone()).into());
+    }
+
+    if !txn_result.is_ok {
+        let fee_payer = *sanitized_transaction.message().fee_payer();
+        txn_result.rollback_accounts = match rollback_accounts {
+            RollbackAccounts::FeePayerOnly { fee_payer_account } => {
+                vec![(fee_payer, fee_payer_account.clone()).into()]
+            }
+            RollbackAccounts::SameNonceAndFeePayer { nonce } => {
+                vec![(*nonce.address(), nonce.account().clone()).into()]
+            }
+            RollbackAccounts::SeparateNonceAndFeePayer {
+                nonce,
+                fee_payer_account,
+            } => vec![
+                (fee_payer, fee_payer_account.clone()).into(),
+                (*nonce.address(), nonce.account().clone()).into(),
+            ],
+        };
+    }
+
+    if let Ok(ProcessedTransaction::FeesOnly(_)) = processing_result {
+        txn_result.resulting_state = Some(ResultingState {
+            acct_states: txn_result.rollback_accounts.clone(),
+            rent_debits: vec![],
+            transaction_rent: 0,
+            acct_provenance: vec![],
+        });
+    }
+}
+
+/// Only keeps the accounts referenced by the transaction in its resulting
+/// state, and fills in the executable accounts Agave omits.
+pub(crate) fn prune_resulting_state(
+    resulting_state: &mut ResultingState,
+    sanitized_transaction: &SanitizedTransaction,
+    bank: &Bank,
+) {
+    let mut loaded_account_keys = HashSet::<Pubkey>::new();
+    loaded_account_keys.extend(sanitized_transaction.message().account_keys().iter());
+
+    // Only keep accounts that were passed in as account_keys or as ALUT accounts
+    resulting_state.acct_states.retain(|account| {
+        let pubkey = Pubkey::new_from_array(account.address.clone().try_into().unwrap());
+        loaded_account_keys.contains(&pubkey) && pubkey != sysvar::instructions::id()
+    });
+
+    // Fill values for executable accounts with no lamports reported in output (this metadata was omitted by Agave for performance reasons)
+    let mut patched_accounts = HashSet::<Pubkey>::new();
+    for account in resulting_state.acct_states.iter_mut() {
+        if account.lamports == 0 && account.executable {
+            let pubkey = Pubkey::new_from_array(account.address.clone().try_into().unwrap());
+            if let Some(account_data) = bank.get_account(&pubkey) {
+                account.lamports = account_data.lamports();
+                account.data = account_data.data().to_vec();
+                account.rent_epoch = account_data.rent_epoch();
+                patched_accounts.insert(pubkey);
+            }
+        }
+    }
+
+    resulting_state.acct_provenance = resulting_state
+        .acct_states
+        .iter()
+        .map(|account| {
+            let pubkey = Pubkey::new_from_array(account.address.clone().try_into().unwrap());
+            get_account_provenance(
+                sanitized_transaction.message(),
+                &pubkey,
+                patched_accounts.contains(&pubkey),
+            ) as i32
+        })
+        .collect();
+}
+
+/// How an account of the resulting state was loaded. Patched accounts are
+/// reported as such whatever their key, since their state comes from the bank
+/// rather than from the execution.
+fn get_account_provenance(
+    message: &SanitizedMessage,
+    pubkey: &Pubkey,
+    patched: bool,
+) -> proto::AccountProvenance {
+    if patched {
+        return proto::AccountProvenance::Patched;
+    }
+    let is_program = message
+        .account_keys()
+        .iter()
+        .position(|key| key == pubkey)
+        .is_some_and(|index| message.is_invoked(index));
+    if is_program {
+        return proto::AccountProvenance::Program;
+    }
+    match message {
+        SanitizedMessage::V0(message) if message.loaded_addresses.writable.contains(pubkey) => {
+            proto::AccountProvenance::AltWritable
+        }
+        SanitizedMessage::V0(message) if message.loaded_addresses.readonly.contains(pubkey) => {
+            proto::AccountProvenance::AltReadonly
+        }
+        _ => proto::AccountProvenance::StaticKey,
+    }
+}
+
+/// Genesis config and features a bank template was built for.
+#[derive(PartialEq)]
+struct BankTemplateKey {
+    active_features: Vec<Pubkey>,
+    rent: Rent,
+    epoch_schedule: EpochSchedule,
+    genesis_hash: Hash,
+}
+
+/// Frozen genesis bank which inputs fork from. Forks are removed once their
+/// input is done, so the template only ever holds genesis state.
+struct BankTemplate {
+    key: BankTemplateKey,
+    bank_forks: Arc<RwLock<BankForks>>,
+    environments: ProgramRuntimeEnvironments,
+}
+
+/// Inputs usually share a handful of feature sets, keep the most recently
+/// used templates only.
+const MAX_BANK_TEMPLATES: usize = 4;
+
+thread_local! {
+    static BANK_TEMPLATES: RefCell<Vec<BankTemplate>> = RefCell::new(Vec::new());
+    static BANK_TEMPLATES_ENABLED: Cell<bool> = Cell::new(true);
+}
+
+/// Enables or disables forking inputs from cached genesis banks (enabled by
+/// default). Disabling drops the cached templates.
+pub fn set_bank_templates_enabled(enabled: bool) {
+    BANK_TEMPLATES_ENABLED.with(|templates_enabled| templates_enabled.set(enabled));
+    if !enabled {
+        BANK_TEMPLATES.with(|templates| templates.borrow_mut().clear());
+    }
+}
+
+/// Bank an input executes on. When forked from a template, dropping it
+/// removes the forks created for the input, and restores the template once no
+/// other session forks it.
+pub(crate) struct BankSession {
+    pub(crate) bank_forks: Arc<RwLock<BankForks>>,
+    pub(crate) bank: Arc<Bank>,
+    /// Slots of the banks created for the input, in creation order
+    forks: Vec<Slot>,
+    template_environments: Option<ProgramRuntimeEnvironments>,
+}
+
+impl BankSession {
+    /// Freezes the current bank and continues on a child bank at `slot`. The
+    /// program cache is pruned to the child bank like rooting it would, which
+    /// moves it to the new epoch when crossing an epoch boundary. Agave's new
+    /// epoch processing runs as well.
+    pub(crate) fn advance_to_slot(&mut self, slot: Slot, fee_collector: &Pubkey) {
+        self.bank.freeze();
+        let parent = self.bank.clone();
+        let new_bank = Bank::new_from_parent(parent.clone(), fee_collector, slot);
+        self.bank = self
+            .bank_forks
+            .write()
+            .unwrap()
+            .insert(new_bank)
+            .clone_without_scheduler();
+        self.forks.push(slot);
+        {
+            let mut program_cache = self
+                .bank
+                .get_transaction_processor()
+                .program_cache
+                .write()
+                .unwrap();
+            program_cache.prune(slot, self.bank.epoch());
+            /* Features activated by the new epoch may change the syscalls and VM config */
+            if self.bank.feature_set.active != parent.feature_set.active {
+                let compute_budget = self.bank.compute_budget().unwrap_or_default();
+                let program_runtime_v1 = create_program_runtime_environment_v1(
+                    &self.bank.feature_set,
+                    &compute_budget,
+                    false, /* deployment */
+                    false, /* debugging_features */
+                )
+                .unwrap();
+                program_cache.environments = ProgramRuntimeEnvironments {
+                    program_runtime_v1: Arc::new(program_runtime_v1),
+                    program_runtime_v2: program_cache.environments.program_runtime_v2.clone(),
+                };
+            }
+        }
+        self.bank.get_transaction_processor().reset_sysvar_cache();
+        self.bank
+            .get_transaction_processor()
+            .fill_missing_sysvar_cache_entries(self.bank.as_ref());
+    }
+}
+
+impl Drop for BankSession {
+    fn drop(&mut self) {
+        let Some(environments) = self.template_environments.take() else {
+            return;
+        };
+
+        /* Dropping an unrooted bank purges its slot from the accounts DB. The
+        session's bank is released first so removing the forks drops their last
+        references. */
+        let root_bank = {
+            let mut bank_forks = self.bank_forks.write().unwrap();
+            let root_bank = bank_forks.root_bank();
+            drop(std::mem::replace(&mut self.bank, root_bank.clone()));
+            for slot in self.forks.drain(..).rev() {
+                drop(bank_forks.remove(slot));
+            }
+            /* The status and program caches are shared with the forks of other sessions */
+            if bank_forks.banks().len() > 1 {
+                return;
+            }
+            root_bank
+        };
+        root_bank.clear_signatures();
+
+        let mut program_cache = root_bank
+            .get_transaction_processor()
+            .program_cache
+            .write()
+            .unwrap();
+        let loaded_programs: Vec<Pubkey> = program_cache
+            .get_flattened_entries(true, true)
+            .into_iter()
+            .filter(|(_, entry)| !matches!(entry.program, ProgramCacheEntryType::Builtin(_)))
+            .map(|(pubkey, _)| pubkey)
+            .collect();
+        program_cache.remove_programs(loaded_programs.into_iter());
+        /* Rewind the pruning done for the forks, the next input may be at an earlier slot */
+        program_cache.latest_root_slot = root_bank.slot();
+        program_cache.latest_root_epoch = root_bank.epoch();
+        program_cache.upcoming_environments = None;
+        program_cache.environments = environments;
+    }
+}
+
+/// Accounts DB directories of every thread, removed when dropped.
+static ACCOUNTS_DB_DIRS: Mutex<Vec<TempDir>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static ACCOUNTS_DB_DIR: RefCell<Option<PathBuf>> = RefCell::new(None);
+}
+
+/// Working directory shared by the accounts DBs of the banks created by the
+/// current thread, like the bank templates, on tmpfs. Banks are never rooted
+/// past genesis, so accounts only ever live in the accounts DB write cache and
+/// no storage file is written. The directory merely keeps the accounts DB from
+/// creating temporary directories for every bank. Fails rather than using the
+/// disk if tmpfs is not available.
+pub fn accounts_db_dir() -> std::io::Result<PathBuf> {
+    ACCOUNTS_DB_DIR.with(|accounts_db_dir| {
+        let mut accounts_db_dir = accounts_db_dir.borrow_mut();
+        if accounts_db_dir.is_none() {
+            let shm = Path::new("/dev/shm");
+            if !shm.is_dir() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "/dev/shm is not available",
+                ));
+            }
+            let temp_dir = tempfile::Builder::new()
+                .prefix("solfuzz-agave-accounts-")
+                .tempdir_in(shm)?;
+            std::fs::create_dir(temp_dir.path().join("accounts"))?;
+            std::fs::create_dir(temp_dir.path().join("accounts_hash_cache"))?;
+            *accounts_db_dir = Some(temp_dir.path().to_path_buf());
+            ACCOUNTS_DB_DIRS.lock().unwrap().push(temp_dir);
+        }
+        Ok(accounts_db_dir.clone().unwrap())
+    })
+}
+
+/// Drops the bank templates of the current thread and removes its accounts
+/// DB directory. The next bank created by the thread starts over.
+pub fn remove_accounts_db_dir() {
+    BANK_TEMPLATES.with(|templates| templates.borrow_mut().clear());
+    if let Some(path) = ACCOUNTS_DB_DIR.with(|accounts_db_dir| accounts_db_dir.take()) {
+        ACCOUNTS_DB_DIRS
+            .lock()
+            .unwrap()
+            .retain(|temp_dir| temp_dir.path() != path);
+    }
+}
+
+/// Removes the accounts DB directories of every thread. No other thread may
+/// execute inputs afterwards.
+pub fn remove_accounts_db_dirs() {
+    remove_accounts_db_dir();
+    ACCOUNTS_DB_DIRS.lock().unwrap().clear();
+}
+
+#[allow(deprecated)]
+fn new_genesis_bank(
+    genesis_config: &GenesisConfig,
+    genesis_hash: Hash,
+    feature_set: &FeatureSet,
+    fee_collector: &Pubkey,
+    fee_structure: Option<&FeeStructure>,
+) -> std::io::Result<Arc<RwLock<BankForks>>> {
+    let accounts_db_dir = accounts_db_dir()?;
     let index = Some(AccountsIndexConfig {
         bins: Some(2),
         flush_threads: Some(1),
@@ -412,14 +1021,16 @@ pub fn execute_transaction(context: TxnContext) -> Option<TxnResult> {
     });
     let accounts_db_config = Some(AccountsDbConfig {
         index,
+        base_working_path: Some(accounts_db_dir.clone()),
+        accounts_hash_cache_path: Some(accounts_db_dir.join("accounts_hash_cache")),
         storage_access: StorageAccess::File,
         skip_initial_hash_calc: true,
         ..AccountsDbConfig::default()
     });
-    let bank = Bank::new_with_paths(
-        &genesis_config,
+    let mut bank = Bank::new_with_paths(
+        genesis_config,
         Arc::new(RuntimeConfig::default()),
-        vec![],
+        vec![accounts_db_dir.join("accounts")],
         None,
         None,
         AccountSecondaryIndexes::default(),
@@ -427,18 +1038,151 @@ pub fn execute_transaction(context: TxnContext) -> Option<TxnResult> {
         false,
         accounts_db_config,
         None,
-        Some(fee_collector),
+        Some(*fee_collector),
         Arc::new(AtomicBool::new(false)),
-        genesis_hash,
+        Some(genesis_hash),
         Some(feature_set.clone()),
     );
+    if let Some(fee_structure) = fee_structure {
+        bank.set_fee_structure(fee_structure);
+    }
+    /* Observe the instructions executed by the builtins, skipping the ones
+    already migrated to Core BPF */
+    for builtin in BUILTINS.iter() {
+        if let Some(enable_feature_id) = builtin.enable_feature_id {
+            if !bank.feature_set.is_active(&enable_feature_id) {
+                continue;
+            }
+        }
+        let is_native = bank
+            .get_account(&builtin.program_id)
+            .is_some_and(|account| native_loader::check_id(account.owner()));
+        if !is_native {
+            continue;
+        }
+        bank.add_builtin(
+            builtin.program_id,
+            builtin.name,
+            ProgramCacheEntry::new_builtin(
+                0,
+                builtin.name.len(),
+                observe_entrypoint(builtin.entrypoint),
+            ),
+        );
+    }
     let bank_forks = BankForks::new_rw_arc(bank);
-    let mut bank = bank_forks.read().unwrap().root_bank();
-    bank.rehash();
+    bank_forks.read().unwrap().root_bank().rehash();
+    Ok(bank_forks)
+}
+
+/// Returns the bank forks of the template matching `key`, building it first
+/// if needed, along with the template's program runtime environments.
+fn get_bank_template(
+    key: BankTemplateKey,
+    genesis_config: &GenesisConfig,
+    feature_set: &FeatureSet,
+) -> std::io::Result<(Arc<RwLock<BankForks>>, ProgramRuntimeEnvironments)> {
+    BANK_TEMPLATES.with(|templates| {
+        let mut templates = templates.borrow_mut();
+        let template = match templates.iter().position(|template| template.key == key) {
+            Some(index) => templates.remove(index),
+            None => {
+                let bank_forks = new_genesis_bank(
+                    genesis_config,
+                    key.genesis_hash,
+                    feature_set,
+                    &Pubkey::default(),
+                    None,
+                )?;
+                let root_bank = bank_forks.read().unwrap().root_bank();
+                root_bank.freeze();
+                let environments = root_bank
+                    .get_transaction_processor()
+                    .program_cache
+                    .read()
+                    .unwrap()
+                    .environments
+                    .clone();
+                BankTemplate {
+                    key,
+                    bank_forks,
+                    environments,
+                }
+            }
+        };
+        let result = (template.bank_forks.clone(), template.environments.clone());
+
+        /* Most recently used last */
+        templates.push(template);
+        if templates.len() > MAX_BANK_TEMPLATES {
+            templates.remove(0);
+        }
+        Ok(result)
+    })
+}
+
+/// Creates a bank at `slot` holding `accounts` on top of the builtins, with
+/// the sysvar caches filled and `blockhash_queue` registered. Unless `slot` is
+/// the genesis slot, the bank is forked from a cached genesis bank. The fee
+/// structure, if any, replaces the default one and its lamports per signature
+/// are registered along with the blockhashes. Returns `None` if the accounts
+/// DB directory cannot be created.
+#[allow(deprecated)]
+pub(crate) fn setup_bank(
+    feature_set: &FeatureSet,
+    accounts: &[(Pubkey, &AcctState)],
+    blockhash_queue: Vec<Vec<u8>>,
+    slot: Slot,
+    fee_collector: &Pubkey,
+    fee_structure: Option<&FeeStructure>,
+) -> Option<BankSession> {
+    /* HACK: Set the genesis config rent and epoch schedule from the "to-be" sysvars, if present */
+    let rent: Rent = accounts
+        .iter()
+        .find(|(pubkey, account)| *pubkey == sysvar::rent::id() && account.lamports > 0)
+        .map(|(_, account)| bincode::deserialize(&account.data).ok())
+        .unwrap_or_default()
+        .unwrap_or_default();
+    let epoch_schedule: EpochSchedule = accounts
+        .iter()
+        .find(|(pubkey, account)| *pubkey == sysvar::epoch_schedule::id() && account.lamports > 0)
+        .map(|(_, account)| bincode::deserialize(&account.data).ok())
+        .unwrap_or_default()
+        .unwrap_or_default();
+
+    let genesis_config = GenesisConfig {
+        creation_time: 0,
+        rent: rent.clone(),
+        epoch_schedule: epoch_schedule.clone(),
+        ..GenesisConfig::default()
+    };
 
-    if slot > 0 {
-        let new_bank = Bank::new_from_parent(bank.clone(), &fee_collector, slot);
-        bank = bank_forks
+    let mut blockhash_queue = if blockhash_queue.is_empty() {
+        vec![vec![0u8; 32]]
+    } else {
+        blockhash_queue
+    };
+    let genesis_hash = Hash::new(blockhash_queue[0].as_slice());
+
+    let session = if slot > 0 && BANK_TEMPLATES_ENABLED.with(Cell::get) {
+        let mut active_features: Vec<Pubkey> = feature_set.active.keys().copied().collect();
+        active_features.sort();
+        let key = BankTemplateKey {
+            active_features,
+            rent,
+            epoch_schedule,
+            genesis_hash,
+        };
+        let (bank_forks, environments) =
+            get_bank_template(key, &genesis_config, feature_set).ok()?;
+
+        /* The template's program cache only holds builtins, which are valid on every fork */
+        let root_bank = bank_forks.read().unwrap().root_bank();
+        let mut new_bank = Bank::new_from_parent(root_bank, fee_collector, slot);
+        if let Some(fee_structure) = fee_structure {
+            new_bank.set_fee_structure(fee_structure);
+        }
+        let bank = bank_forks
             .write()
             .unwrap()
             .insert(new_bank)
@@ -448,14 +1192,45 @@ pub fn execute_transaction(context: TxnContext) -> Option<TxnResult> {
             .write()
             .unwrap()
             .prune(slot, bank.epoch());
-    }
+        BankSession {
+            bank_forks,
+            bank,
+            forks: vec![slot],
+            template_environments: Some(environments),
+        }
+    } else {
+        // Bank on slot 0
+        let bank_forks = new_genesis_bank(
+            &genesis_config,
+            genesis_hash,
+            feature_set,
+            fee_collector,
+            fee_structure,
+        )
+        .ok()?;
+        let mut bank = bank_forks.read().unwrap().root_bank();
 
-    let account_keys = context
-        .tx
-        .as_ref()
-        .and_then(|tx| tx.message.as_ref())
-        .map(|message| message.account_keys.clone())
-        .unwrap_or_default();
+        if slot > 0 {
+            let new_bank = Bank::new_from_parent(bank.clone(), fee_collector, slot);
+            bank = bank_forks
+                .write()
+                .unwrap()
+                .insert(new_bank)
+                .clone_without_scheduler();
+            bank.get_transaction_processor()
+                .program_cache
+                .write()
+                .unwrap()
+                .prune(slot, bank.epoch());
+        }
+        BankSession {
+            bank_forks,
+            bank,
+            forks: vec![],
+            template_environments: None,
+        }
+    };
+    let bank = &session.bank;
 
     /* Save loaded builtins so we don't load them twice */
     let mut stored_accounts = HashSet::<Pubkey>::default();
@@ -470,16 +1245,15 @@ pub fn execute_transaction(context: TxnContext) -> Option<TxnResult> {
     }
 
     /* Load accounts + sysvars
-    NOTE: Like in FD, we store the first instance of an account's state for a given pubkey. Account states of already-seen
-    pubkeys are ignored. */
+    NOTE: Duplicate account states were already resolved according to the duplicate account policy (by default, like
+    in FD, the first instance of an account's state for a given pubkey is kept). Builtins are never overwritten. */
     bank.get_transaction_processor().reset_sysvar_cache();
-    for account in &context.tx.as_ref()?.message.as_ref()?.account_shared_data {
-        let pubkey = Pubkey::new_from_array(account.address.clone().try_into().ok()?);
-        if !stored_accounts.insert(pubkey) {
+    for (pubkey, account) in accounts.iter() {
+        if !stored_accounts.insert(*pubkey) {
             continue;
         }
-        let account_data = AccountSharedData::from(account);
-        bank.store_account(&pubkey, &account_data);
+        let account_data = AccountSharedData::from(*account);
+        bank.store_account(pubkey, &account_data);
     }
     bank.get_transaction_processor()
         .fill_missing_sysvar_cache_entries(bank.as_ref());
@@ -489,8 +1263,9 @@ pub fn execute_transaction(context: TxnContext) -> Option<TxnResult> {
     bank.update_rent();
 
     let sysvar_recent_blockhashes = bank.get_sysvar_cache_for_tests().get_recent_blockhashes();
-    let mut lamports_per_signature: Option<u64> = None;
-    if let Ok(recent_blockhashes) = &sysvar_recent_blockhashes {
+    let mut lamports_per_signature =
+        fee_structure.map(|fee_structure| fee_structure.lamports_per_signature);
+    if let (None, Ok(recent_blockhashes)) = (lamports_per_signature, &sysvar_recent_blockhashes) {
         if let Some(hash) = recent_blockhashes.first() {
             if hash.fee_calculator.lamports_per_signature != 0 {
                 lamports_per_signature = Some(hash.fee_calculator.lamports_per_signature);
@@ -508,120 +1283,438 @@ pub fn execute_transaction(context: TxnContext) -> Option<TxnResult> {
     bank.get_transaction_processor()
         .fill_missing_sysvar_cache_entries(bank.as_ref());
 
-    let message = build_versioned_message(context.tx.as_ref()?.message.as_ref()?)?;
+    Some(session)
+}
 
-    let mut signatures = context
-        .tx
-        .as_ref()?
-        .signatures
+/// Sysvars updated on epoch boundaries, as seen by the transaction.
+fn get_epoch_sysvars(bank: &Bank) -> Vec<AcctState> {
+    [
+        sysvar::clock::id(),
+        sysvar::epoch_rewards::id(),
+        sysvar::epoch_schedule::id(),
+        sysvar::last_restart_slot::id(),
+        sysvar::rent::id(),
+        sysvar::stake_history::id(),
+    ]
+    .into_iter()
+    .filter_map(|pubkey| {
+        bank.get_account(&pubkey)
+            .map(|account| AcctState::from((pubkey, account)))
+    })
+    .collect()
+}
+
+/// Addresses loaded from the lookup tables of a v0 message.
+fn get_loaded_addresses(
+    sanitized_transaction: &SanitizedTransaction,
+) -> Option<proto::LoadedAddresses> {
+    match sanitized_transaction.message() {
+        SanitizedMessage::V0(message) => Some(proto::LoadedAddresses {
+            writable: message
+                .loaded_addresses
+                .writable
+                .iter()
+                .map(|pubkey| pubkey.to_bytes().to_vec())
+                .collect(),
+            readonly: message
+                .loaded_addresses
+                .readonly
+                .iter()
+                .map(|pubkey| pubkey.to_bytes().to_vec())
+                .collect(),
+        }),
+        SanitizedMessage::Legacy(_) => None,
+    }
+}
+
+/// Finds the first lookup table the bank failed to resolve and why. Agave
+/// reports a deactivated table as not found, the table state tells them apart.
+fn get_address_lookup_table_error(
+    bank: &Bank,
+    address_table_lookups: &[MessageAddressTableLookup],
+    error: &TransactionError,
+) -> Option<(proto::AltError, usize)> {
+    if !matches!(
+        error,
+        TransactionError::AddressLookupTableNotFound
+            | TransactionError::InvalidAddressLookupTableOwner
+            | TransactionError::InvalidAddressLookupTableData
+            | TransactionError::InvalidAddressLookupTableIndex
+    ) {
+        return None;
+    }
+
+    let slot_hashes = bank.get_sysvar_cache_for_tests().get_slot_hashes().ok()?;
+    address_table_lookups
         .iter()
-        .map(|item| {
-            Signature::from(<Vec<u8> as TryInto<[u8; 64]>>::try_into(item.clone()).unwrap())
+        .enumerate()
+        .find_map(|(index, lookup)| {
+            let Some(account) = bank.get_account(&lookup.account_key) else {
+                return Some((proto::AltError::MissingTable, index));
+            };
+            if *account.owner() != address_lookup_table::program::id() {
+                return Some((proto::AltError::InvalidOwner, index));
+            }
+            let Ok(table) = AddressLookupTable::deserialize(account.data()) else {
+                return Some((proto::AltError::InvalidData, index));
+            };
+            if matches!(
+                table.meta.status(bank.slot(), &slot_hashes),
+                LookupTableStatus::Deactivated
+            ) {
+                return Some((proto::AltError::DeactivatedTable, index));
+            }
+            [&lookup.writable_indexes, &lookup.readonly_indexes]
+                .into_iter()
+                .any(|indexes| table.lookup(bank.slot(), indexes, &slot_hashes).is_err())
+                .then_some((proto::AltError::IndexOutOfBounds, index))
         })
-        .collect::<Vec<Signature>>();
-    if signatures.is_empty() {
-        // Default: valid txn with 1 empty signature (this keeps tests simpler)
-        signatures.push(Signature::default());
-    }
+}
 
-    let versioned_transaction = VersionedTransaction {
-        message,
-        signatures,
+/// Cost model breakdown of a transaction, checked against the block and
+/// account limits of an empty block. Executed transactions are costed from
+/// their consumed compute units and loaded accounts data size, like replay
+/// does, others from their requested compute budget.
+fn get_transaction_cost(
+    sanitized_transaction: &SanitizedTransaction,
+    feature_set: &FeatureSet,
+    txn_result: &TxnResult,
+) -> proto::TxnCost {
+    let cost = if txn_result.executed {
+        CostModel::calculate_cost_for_executed_transaction(
+            sanitized_transaction,
+            txn_result.executed_units,
+            txn_result.loaded_accounts_data_size as u32,
+            feature_set,
+        )
+    } else {
+        CostModel::calculate_cost(sanitized_transaction, feature_set)
     };
+    let cost_tracker_result = CostTracker::default().try_add(&cost);
 
-    let sanitized_transaction = match bank.verify_transaction(
-        versioned_transaction,
-        TransactionVerificationMode::HashAndVerifyPrecompiles,
-    ) {
-        Ok(v) => v,
-        Err(e) => {
-            let err = bincode::serialize(&e).unwrap_or(vec![0, 0, 0, 0]);
-            let status = u32::from_le_bytes(err.try_into().unwrap()) + 1;
-            return Some(TxnResult {
-                executed: false,
-                sanitization_error: true,
-                resulting_state: None,
-                rent: 0,
-                is_ok: false,
-                status,
-                instruction_error: 0,
-                instruction_error_index: 0,
-                custom_error: 0,
-                return_data: vec![],
-                executed_units: 0,
-                fee_details: None,
-            });
-        }
-    };
+    proto::TxnCost {
+        signature_cost: cost.signature_cost(),
+        write_lock_cost: cost.write_lock_cost(),
+        data_bytes_cost: cost.data_bytes_cost(),
+        programs_execution_cost: cost.programs_execution_cost(),
+        loaded_accounts_data_size_cost: cost.loaded_accounts_data_size_cost(),
+        allocated_accounts_data_size: cost.allocated_accounts_data_size(),
+        total_cost: cost.sum(),
+        is_simple_vote: cost.is_simple_vote(),
+        cost_tracker_status: match cost_tracker_result {
+            Ok(_) => 0,
+            Err(err) => txn_err_to_num(&TransactionError::from(err)),
+        },
+    }
+}
 
-    let transactions = [sanitized_transaction.clone()];
+/// Fee collector used when the input does not set one.
+pub const DEFAULT_FEE_COLLECTOR: Pubkey = Pubkey::new_from_array([0xfc; 32]);
 
-    let batch = bank.prepare_sanitized_batch(&transactions);
+/// Returns the fee collector given as raw bytes, or the default one if empty.
+pub(crate) fn parse_fee_collector(fee_collector: &[u8]) -> Option<Pubkey> {
+    if fee_collector.is_empty() {
+        Some(DEFAULT_FEE_COLLECTOR)
+    } else {
+        Pubkey::try_from(fee_collector).ok()
+    }
+}
+
+/// Returns the fees collected from a transaction and the share of them
+/// deposited to the fee collector when the bank freezes. The rest is burned.
+/// Like Agave, only the transaction fee is subject to the burn once
+/// `reward_full_priority_fee` is active, the whole fee is before. Banks keep
+/// the burn percent of the default fee rate governor set in the genesis
+/// config.
+fn fee_collector_share(fee_details: &proto::FeeDetails, feature_set: &FeatureSet) -> (u64, u64) {
+    let fee_rate_governor = FeeRateGovernor::default();
+    let collected_fees = fee_details
+        .transaction_fee
+        .saturating_add(fee_details.prioritization_fee);
+    let collector_share = if feature_set.is_active(&reward_full_priority_fee::id()) {
+        let (transaction_fee_share, _) = fee_rate_governor.burn(fee_details.transaction_fee);
+        transaction_fee_share.saturating_add(fee_details.prioritization_fee)
+    } else {
+        let (collector_share, _) = fee_rate_governor.burn(collected_fees);
+        collector_share
+    };
+    (collected_fees, collector_share)
+}
 
+/// Processing config shared by the transaction harnesses. Log messages past
+/// `log_messages_bytes_limit` are truncated, no limit applies if unset. Inner
+/// instructions are only recorded if `enable_cpi_recording` is set, since not
+/// every harness reports them.
+pub(crate) fn processing_config(
+    bank: &Bank,
+    log_messages_bytes_limit: Option<usize>,
+    enable_cpi_recording: bool,
+) -> TransactionProcessingConfig {
     let recording_config = ExecutionRecordingConfig {
-        enable_cpi_recording: false,
+        enable_cpi_recording,
         enable_log_recording: true,
         enable_return_data_recording: true,
     };
 
-    let mut timings = ExecuteTimings::default();
-
-    let configs = TransactionProcessingConfig {
+    TransactionProcessingConfig {
         account_overrides: None,
         compute_budget: bank.compute_budget(),
-        log_messages_bytes_limit: None,
+        log_messages_bytes_limit,
         limit_to_load_programs: true,
         recording_config,
         transaction_account_lock_limit: None,
         check_program_modification_slot: false,
+    }
+}
+
+#[allow(deprecated)]
+pub fn execute_transaction(context: TxnContext) -> Option<TxnResult> {
+    let fd_features = context
+        .epoch_ctx
+        .as_ref()
+        .map(|ctx| ctx.features.clone().unwrap_or_default())
+        .unwrap_or_default();
+
+    let feature_set = FeatureSet::from(&fd_features);
+    let fee_collector = parse_fee_collector(&context.fee_collector)?;
+    let slot = context.slot_ctx.as_ref().map(|ctx| ctx.slot).unwrap_or(10); // Arbitrary default > 0
+    let parent_slot = context.slot_ctx.as_ref().and_then(|ctx| ctx.parent_slot);
+    if parent_slot.is_some_and(|parent_slot| parent_slot >= slot) {
+        return None;
+    }
+    let duplicate_account_policy =
+        resolve_duplicate_account_policy(context.duplicate_account_policy(), true);
+
+    /* Wire-format transactions carry no account states, the message may still provide them */
+    let account_states = match context.tx.as_ref().and_then(|tx| tx.message.as_ref()) {
+        Some(message) => message.account_shared_data.as_slice(),
+        None if !context.raw_tx.is_empty() => &[],
+        None => return None,
+    };
+
+    /* Resolve account states sharing an address before anything reads them */
+    let accounts = account_states
+        .iter()
+        .map(|account| {
+            let pubkey = Pubkey::new_from_array(account.address.clone().try_into().ok()?);
+            Some((pubkey, account))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let accounts = dedup_accounts(accounts, duplicate_account_policy).ok()?;
+
+    let blockhash_queue = context.blockhash_queue;
+    let fee_structure = context.fee_structure.as_ref().map(FeeStructure::from);
+
+    /* With a parent slot, the input state is set up there and the transaction executes in the
+    next bank, after the epoch transition if the two slots are in different epochs */
+    let mut session = setup_bank(
+        &feature_set,
+        &accounts,
+        blockhash_queue,
+        parent_slot.unwrap_or(slot),
+        &fee_collector,
+        fee_structure.as_ref(),
+    )?;
+    if parent_slot.is_some() {
+        session.advance_to_slot(slot, &fee_collector);
+    }
+    let bank = &session.bank;
+    let epoch_sysvars = if parent_slot.is_some() {
+        get_epoch_sysvars(bank)
+    } else {
+        vec![]
     };
 
+    if context.profile {
+        let mut program_cache = bank
+            .get_transaction_processor()
+            .program_cache
+            .write()
+            .unwrap();
+        program_cache.environments = profile::profile_environments(&program_cache.environments);
+    }
+
+    /* Wire-format transactions are sanitized along with the others when verified */
+    let versioned_transaction = if context.raw_tx.is_empty() {
+        match build_versioned_transaction(context.tx.as_ref()?) {
+            Ok(versioned_transaction) => versioned_transaction,
+            Err(MessageBuildError::MissingMessage) => return None,
+            Err(error) => {
+                let mut txn_result = message_build_error_result(error);
+                txn_result.duplicate_account_policy = duplicate_account_policy as i32;
+                return Some(txn_result);
+            }
+        }
+    } else {
+        match deserialize_raw_transaction(&context.raw_tx) {
+            Ok(versioned_transaction) => versioned_transaction,
+            Err(_) => {
+                let mut txn_result = deserialization_error_result();
+                txn_result.duplicate_account_policy = duplicate_account_policy as i32;
+                return Some(txn_result);
+            }
+        }
+    };
+
+    /* Signatures are only checked on request, fuzzed signatures are almost never valid */
+    let verification_mode = if context.verify_signatures {
+        TransactionVerificationMode::FullVerification
+    } else {
+        TransactionVerificationMode::HashAndVerifyPrecompiles
+    };
+    let address_table_lookups = versioned_transaction
+        .message
+        .address_table_lookups()
+        .map(<[_]>::to_vec)
+        .unwrap_or_default();
+    let sanitized_transaction =
+        match bank.verify_transaction(versioned_transaction, verification_mode) {
+            Ok(v) => v,
+            Err(e) => {
+                let mut txn_result = sanitization_error_result(&e);
+                txn_result.duplicate_account_policy = duplicate_account_policy as i32;
+                if let Some((alt_error, lookup_index)) =
+                    get_address_lookup_table_error(bank, &address_table_lookups, &e)
+                {
+                    txn_result.set_alt_error(alt_error);
+                    txn_result.alt_error_lookup_index = lookup_index as u32;
+                }
+                return Some(txn_result);
+            }
+        };
+
+    let transactions = [sanitized_transaction.clone()];
+
+    let batch = bank.prepare_sanitized_batch(&transactions);
+
+    let mut timings = ExecuteTimings::default();
+
     let mut metrics = TransactionErrorMetrics::default();
+    entrypoints::start_recording_account_resizes();
     let result = bank.load_and_execute_transactions(
         &batch,
         context.max_age as usize,
         &mut timings,
         &mut metrics,
-        configs,
+        processing_config(
+            bank,
+            context.log_messages_bytes_limit.map(|limit| limit as usize),
+            true,
+        ),
     );
+    let account_resizes = entrypoints::take_account_resizes();
 
-    let mut txn_result: TxnResult = result.into();
+    let mut txn_result = TxnResult::from(&result.processing_results[0]);
+    set_rollback_state(
+        &mut txn_result,
+        &result.processing_results[0],
+        &sanitized_transaction,
+    );
+    txn_result.epoch_sysvars = epoch_sysvars;
+    txn_result.duplicate_account_policy = duplicate_account_policy as i32;
+    txn_result.loaded_addresses = get_loaded_addresses(&sanitized_transaction);
+    if context.profile {
+        txn_result.profile = Some(profile::take_profile(&timings));
+    }
     if let Some(relevant_accounts) = &mut txn_result.resulting_state {
-        let mut loaded_account_keys = HashSet::<Pubkey>::new();
-        loaded_account_keys.extend(
-            account_keys
-                .iter()
-                .map(|key| Pubkey::new_from_array(key.clone().try_into().ok().unwrap())),
-        );
-        match sanitized_transaction.message() {
-            SanitizedMessage::Legacy(_) => {}
-            SanitizedMessage::V0(message) => {
-                loaded_account_keys.extend(message.loaded_addresses.writable.clone().iter());
-                loaded_account_keys.extend(message.loaded_addresses.readonly.clone().iter());
-            }
-        }
-
-        // Only keep accounts that were passed in as account_keys or as ALUT accounts
-        relevant_accounts.acct_states.retain(|account| {
-            let pubkey = Pubkey::new_from_array(account.address.clone().try_into().unwrap());
-            loaded_account_keys.contains(&pubkey) && pubkey != sysvar::instructions::id()
-        });
+        prune_resulting_state(relevant_accounts, &sanitized_transaction, bank);
 
-        // Fill values for executable accounts with no lamports reported in output (this metadata was omitted by Agave for performance reasons)
-        for account in relevant_accounts.acct_states.iter_mut() {
-            if account.lamports == 0 && account.executable {
-                let account_data = bank.get_account(&Pubkey::new_from_array(
-                    account.address.clone().try_into().unwrap(),
-                ));
-                if let Some(account_data) = account_data {
-                    account.lamports = account_data.lamports();
-                    account.data = account_data.data().to_vec();
-                    account.rent_epoch = account_data.rent_epoch();
-                }
-            }
-        }
+        txn_result.account_resizes = account_resizes
+            .into_iter()
+            .map(|(pubkey, data_len_delta)| proto::AccountResize {
+                address: pubkey.to_bytes().to_vec(),
+                data_len_delta,
+            })
+            .collect();
         txn_result.resulting_state = Some(relevant_accounts.clone());
     }
 
+    txn_result.cost = Some(get_transaction_cost(
+        &sanitized_transaction,
+        &bank.feature_set,
+        &txn_result,
+    ));
+
+    bank.commit_transactions(
+        &transactions,
+        result.processing_results,
+        &result.processed_counts,
+        &mut timings,
+    );
+    /* Fees are distributed to the collector when the bank freezes. The
+    transaction itself may have changed the collector balance, so only the
+    freeze is measured. */
+    let collector_balance = bank.get_balance(&fee_collector);
+    bank.freeze();
+    let (collected_fees, collector_share) = txn_result
+        .fee_details
+        .as_ref()
+        .map(|fee_details| fee_collector_share(fee_details, &bank.feature_set))
+        .unwrap_or_default();
+    let rewarded = bank
+        .get_balance(&fee_collector)
+        .saturating_sub(collector_balance)
+        .min(collector_share);
+    /* A rejected deposit burns the collector share as well */
+    let burned = collected_fees.saturating_sub(rewarded);
+    txn_result.fee_distribution = Some(proto::FeeDistribution {
+        fee_collector: fee_collector.to_bytes().to_vec(),
+        collected_fees,
+        rewarded,
+        burned,
+        collector_balance: bank.get_balance(&fee_collector),
+    });
+
     Some(txn_result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::system_account;
+
+    #[test]
+    fn test_bank_sessions_isolated() {
+        let feature_set = FeatureSet::default();
+        let pubkey = Pubkey::new_unique();
+        let first_state = system_account(&pubkey, 1000);
+        let second_state = system_account(&pubkey, 2000);
+
+        // Both sessions fork the same template and are alive at the same time
+        let first = setup_bank(
+            &feature_set,
+            &[(pubkey, &first_state)],
+            vec![],
+            10,
+            &Pubkey::default(),
+            None,
+        )
+        .unwrap();
+        let second = setup_bank(
+            &feature_set,
+            &[(pubkey, &second_state)],
+            vec![],
+            11,
+            &Pubkey::default(),
+            None,
+        )
+        .unwrap();
+        assert!(Arc::ptr_eq(&first.bank_forks, &second.bank_forks));
+        assert_eq!(first.bank.get_balance(&pubkey), 1000);
+        assert_eq!(second.bank.get_balance(&pubkey), 2000);
+        assert_eq!(first.bank.parent_slot(), 0);
+        assert_eq!(second.bank.parent_slot(), 0);
+
+        // Dropping a session leaves the forks of the other one alone
+        let bank_forks = first.bank_forks.clone();
+        drop(first);
+        assert!(bank_forks.read().unwrap().get(10).is_none());
+        assert!(bank_forks.read().unwrap().get(11).is_some());
+        assert_eq!(second.bank.get_balance(&pubkey), 2000);
+
+        drop(second);
+        assert_eq!(bank_forks.read().unwrap().banks().len(), 1);
+        let third = setup_bank(&feature_set, &[], vec![], 12, &Pubkey::default(), None).unwrap();
+        assert_eq!(third.bank.get_balance(&pubkey), 0);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::proto::{self, TxnParseContext, TxnParseEffects};
+use crate::txn_fuzzer::deserialize_raw_transaction;
+use crate::utils::err_map::txn_err_to_num;
+use prost::Message;
+use solana_program::hash::Hash;
+use solana_program::message::{v0::LoadedAddresses, SimpleAddressLoader};
+use solana_sdk::reserved_account_keys::ReservedAccountKeys;
+use solana_sdk::transaction::{
+    SanitizedTransaction, SanitizedVersionedTransaction, TransactionError, VersionedTransaction,
+};
+use std::ffi::c_int;
+
+/// Account lock limit applied when the input does not set one, i.e. the
+/// limit before `increase_tx_account_lock_limit` is activated.
+const DEFAULT_ACCOUNT_LOCK_LIMIT: usize = 64;
+
+#[no_mangle]
+pub unsafe extern "C" fn sol_compat_txn_parse_v1(
+    out_ptr: *mut u8,
+    out_psz: *mut u64,
+    in_ptr: *mut u8,
+    in_sz: u64,
+) -> c_int {
+    let in_slice = std::slice::from_raw_parts(in_ptr, in_sz as usize);
+    let parse_context = match TxnParseContext::decode(in_slice) {
+        Ok(context) => context,
+        Err(_) => return 0,
+    };
+
+    let parse_effects = parse_transaction(&parse_context);
+
+    let out_slice = std::slice::from_raw_parts_mut(out_ptr, (*out_psz) as usize);
+    let out_bytes = parse_effects.encode_to_vec();
+    if out_bytes.len() > out_slice.len() {
+        return 0;
+    }
+    out_slice[..out_bytes.len()].copy_from_slice(&out_bytes);
+    *out_psz = out_bytes.len() as u64;
+
+    1
+}
+
+/// Parses a wire-format transaction like Agave does before scheduling it.
+/// Address lookup tables are not resolved, so only the static account keys
+/// are subject to the account lock checks (duplicate keys, then the lock
+/// limit). The parsed transaction is reported even if a later check fails.
+pub fn parse_transaction(input: &TxnParseContext) -> TxnParseEffects {
+    let versioned_transaction = match deserialize_raw_transaction(&input.raw_tx) {
+        Ok(versioned_transaction) => versioned_transaction,
+        Err(_) => {
+            return TxnParseEffects {
+                deserialization_error: true,
+                ..TxnParseEffects::default()
+            }
+        }
+    };
+
+    let message_hash = versioned_transaction.message.hash();
+    let tx = proto::SanitizedTransaction {
+        message: Some((&versioned_transaction.message).into()),
+        message_hash: message_hash.to_bytes().to_vec(),
+        is_simple_vote_tx: false,
+        signatures: versioned_transaction
+            .signatures
+            .iter()
+            .map(|signature| signature.as_ref().to_vec())
+            .collect(),
+    };
+
+    let account_lock_limit = match input.account_lock_limit {
+        0 => DEFAULT_ACCOUNT_LOCK_LIMIT,
+        limit => limit as usize,
+    };
+    let status = match check_transaction(versioned_transaction, message_hash, account_lock_limit) {
+        Ok(()) => 0,
+        Err(error) => txn_err_to_num(&error),
+    };
+
+    TxnParseEffects {
+        deserialization_error: false,
+        status,
+        tx: Some(tx),
+    }
+}
+
+fn check_transaction(
+    versioned_transaction: VersionedTransaction,
+    message_hash: Hash,
+    account_lock_limit: usize,
+) -> Result<(), TransactionError> {
+    let sanitized_versioned_transaction =
+        SanitizedVersionedTransaction::try_from(versioned_transaction)?;
+    let is_simple_vote_tx = false;
+    let sanitized_transaction = SanitizedTransaction::try_new(
+        sanitized_versioned_transaction,
+        message_hash,
+        is_simple_vote_tx,
+        SimpleAddressLoader::Enabled(LoadedAddresses::default()),
+        &ReservedAccountKeys::empty_key_set(),
+    )?;
+    SanitizedTransaction::validate_account_locks(
+        sanitized_transaction.message(),
+        account_lock_limit,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::message::{legacy, VersionedMessage};
+    use solana_program::pubkey::Pubkey;
+    use solana_sdk::packet::PACKET_DATA_SIZE;
+    use solana_sdk::signature::Signature;
+    use solana_sdk::system_instruction;
+
+    fn transfer_transaction(memo_len: usize) -> VersionedTransaction {
+        let from = Pubkey::new_unique();
+        let mut instruction = system_instruction::transfer(&from, &Pubkey::new_unique(), 1);
+        instruction
+            .data
+            .resize(instruction.data.len() + memo_len, 0);
+        VersionedTransaction {
+            signatures: vec![Signature::new_unique()],
+            message: VersionedMessage::Legacy(legacy::Message::new(&[instruction], Some(&from))),
+        }
+    }
+
+    fn parse_input(versioned_transaction: &VersionedTransaction) -> TxnParseContext {
+        TxnParseContext {
+            raw_tx: bincode::serialize(versioned_transaction).unwrap(),
+            account_lock_limit: 0,
+        }
+    }
+
+    #[test]
+    fn test_parse_transaction() {
+        let versioned_transaction = transfer_transaction(0);
+        let mut input = parse_input(&versioned_transaction);
+
+        let effects = parse_transaction(&input);
+        assert!(!effects.deserialization_error);
+        assert_eq!(effects.status, 0);
+        let parsed_tx = effects.tx.unwrap();
+        assert_eq!(
+            parsed_tx.signatures,
+            vec![versioned_transaction.signatures[0].as_ref().to_vec()]
+        );
+        assert_eq!(
+            parsed_tx.message_hash,
+            versioned_transaction.message.hash().to_bytes().to_vec()
+        );
+        let parsed_message = parsed_tx.message.unwrap();
+        assert_eq!(
+            parsed_message.account_keys,
+            versioned_transaction
+                .message
+                .static_account_keys()
+                .iter()
+                .map(|key| key.to_bytes().to_vec())
+                .collect::<Vec<_>>()
+        );
+
+        // 3 account keys, over the lock limit
+        input.account_lock_limit = 2;
+        let effects = parse_transaction(&input);
+        assert_eq!(effects.status, 23); // TooManyAccountLocks
+        assert!(effects.tx.is_some());
+    }
+
+    #[test]
+    fn test_parse_transaction_trailing_bytes() {
+        let mut input = parse_input(&transfer_transaction(0));
+        input.raw_tx.push(0);
+
+        let effects = parse_transaction(&input);
+        assert!(effects.deserialization_error);
+        assert!(effects.tx.is_none());
+    }
+
+    #[test]
+    fn test_parse_transaction_packet_size() {
+        // Well-formed, but does not fit in a packet
+        let input = parse_input(&transfer_transaction(PACKET_DATA_SIZE));
+        assert!(input.raw_tx.len() > PACKET_DATA_SIZE);
+
+        let effects = parse_transaction(&input);
+        assert!(effects.deserialization_error);
+        assert!(effects.tx.is_none());
+    }
+}
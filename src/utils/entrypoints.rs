@@ -0,0 +1,251 @@
+use crate::proto::SerializedInput;
+use crate::utils::profile;
+use crate::utils::vm::serialization::serialize_instruction_input;
+use lazy_static::lazy_static;
+use solana_program_runtime::{
+    invoke_context::{BuiltinFunctionWithContext, InvokeContext},
+    solana_rbpf::vm::EbpfVm,
+};
+use solana_sdk::{
+    feature_set::bpf_account_data_direct_mapping, pubkey::Pubkey,
+    transaction_context::TransactionContext,
+};
+use std::{
+    cell::{Cell, RefCell},
+    sync::RwLock,
+};
+
+/// Runs `f` on the invoke context of `vm`. The borrow ends when `f` returns,
+/// so it never overlaps with the builtin or syscall invoked through `vm`.
+pub(crate) fn with_invoke_context<R>(
+    vm: *mut EbpfVm<InvokeContext<'static>>,
+    f: impl FnOnce(&InvokeContext<'_>) -> R,
+) -> R {
+    // SAFETY: the VM outlives the builtins and syscalls it invokes, and is not
+    // used by anything else until `f` returns
+    let vm = unsafe { &*vm };
+    f(vm.context_object_pointer)
+}
+
+/// Account data length changes observed during the current execution.
+#[derive(Default)]
+struct AccountResizes {
+    /// Data length of every transaction account at the last observation
+    data_lens: Vec<(Pubkey, usize)>,
+    resizes: Vec<(Pubkey, i64)>,
+}
+
+impl AccountResizes {
+    /// Records the data length change of every account resized since the
+    /// previous observation. The first observation only sets the baseline.
+    fn observe(&mut self, data_lens: Vec<(Pubkey, usize)>) {
+        if self.data_lens.len() == data_lens.len() {
+            for ((pubkey, post_len), (_, pre_len)) in data_lens.iter().zip(self.data_lens.iter()) {
+                let data_len_delta = (*post_len as i64).saturating_sub(*pre_len as i64);
+                if data_len_delta != 0 {
+                    self.resizes.push((*pubkey, data_len_delta));
+                }
+            }
+        }
+        self.data_lens = data_lens;
+    }
+}
+
+thread_local! {
+    static ACCOUNT_RESIZES: RefCell<Option<AccountResizes>> = RefCell::new(None);
+}
+
+/// Starts recording the account resizes of an execution. Resizes are observed
+/// whenever an instruction, including a CPI, enters or leaves a builtin
+/// entrypoint, which is where the runtime applies the data length changes of
+/// BPF programs. A resize undone within an instruction before any CPI is never
+/// applied to the transaction context, so it is not observed either.
+pub fn start_recording_account_resizes() {
+    ACCOUNT_RESIZES.with(|resizes| *resizes.borrow_mut() = Some(AccountResizes::default()));
+}
+
+/// Stops recording and returns the data length changes in the order they
+/// were observed. An account resized several times has one entry per resize.
+pub fn take_account_resizes() -> Vec<(Pubkey, i64)> {
+    ACCOUNT_RESIZES
+        .with(|resizes| resizes.take())
+        .map(|resizes| resizes.resizes)
+        .unwrap_or_default()
+}
+
+fn observe_account_resizes(transaction_context: &TransactionContext) {
+    ACCOUNT_RESIZES.with(|resizes| {
+        let mut resizes = resizes.borrow_mut();
+        let Some(resizes) = resizes.as_mut() else {
+            return;
+        };
+        // Accounts borrowed by the caller are observed once it releases them
+        let Some(data_lens) = (0..transaction_context.get_number_of_accounts())
+            .map(|index| {
+                let pubkey = *transaction_context
+                    .get_key_of_account_at_index(index)
+                    .ok()?;
+                let account = transaction_context.get_account_at_index(index).ok()?;
+                let data_len = account.try_borrow().ok()?.data().len();
+                Some((pubkey, data_len))
+            })
+            .collect::<Option<Vec<_>>>()
+        else {
+            return;
+        };
+        resizes.observe(data_lens);
+    });
+}
+
+thread_local! {
+    static CAPTURE_INPUT_REGION: Cell<bool> = Cell::new(false);
+    static INPUT_REGION: RefCell<Option<SerializedInput>> = RefCell::new(None);
+}
+
+/// Starts capturing the input region the BPF loader hands to the VM of the
+/// next top-level instruction.
+pub fn start_capturing_input_region() {
+    CAPTURE_INPUT_REGION.with(|capture| capture.set(true));
+    INPUT_REGION.with(|input_region| input_region.take());
+}
+
+/// Stops capturing and returns the captured input region, if a BPF program
+/// was executed.
+pub fn take_input_region() -> Option<SerializedInput> {
+    CAPTURE_INPUT_REGION.with(|capture| capture.set(false));
+    INPUT_REGION.with(|input_region| input_region.take())
+}
+
+/// Serializes the input region of the current instruction from the live
+/// transaction context, right before its loader does the same to create the
+/// VM, if the instruction executes a BPF program.
+fn capture_input_region(invoke_context: &InvokeContext<'_>) {
+    if !CAPTURE_INPUT_REGION.with(|capture| capture.replace(false)) {
+        return;
+    }
+    let transaction_context = &invoke_context.transaction_context;
+    let is_bpf_program = transaction_context
+        .get_current_instruction_context()
+        .and_then(|instr_ctx| instr_ctx.try_borrow_last_program_account(transaction_context))
+        .is_ok_and(|program_account| crate::is_bpf_loader(program_account.get_owner()));
+    if !is_bpf_program {
+        return;
+    }
+    let copy_account_data = !invoke_context
+        .get_feature_set()
+        .is_active(&bpf_account_data_direct_mapping::id());
+    let input_region = serialize_instruction_input(transaction_context, copy_account_data).ok();
+    INPUT_REGION.with(|captured| *captured.borrow_mut() = input_region);
+}
+
+/// Calls the builtin entrypoint registered at `INDEX`, observing the
+/// transaction context before and after the instruction it executes. CPIs
+/// are profiled by the syscall wrappers, so only top-level instructions are
+/// profiled and have their input region captured here.
+fn observed_entrypoint<const INDEX: usize>(
+    vm: *mut EbpfVm<InvokeContext<'static>>,
+    arg1: u64,
+    arg2: u64,
+    arg3: u64,
+    arg4: u64,
+    arg5: u64,
+) {
+    let entrypoint = ENTRYPOINTS.read().unwrap()[INDEX];
+
+    let (cu_before, top_level_instruction) = with_invoke_context(vm, |invoke_context| {
+        let transaction_context = &invoke_context.transaction_context;
+        observe_account_resizes(transaction_context);
+        let is_top_level = invoke_context.get_stack_height() == 1;
+        if is_top_level {
+            capture_input_region(invoke_context);
+        }
+        let top_level_instruction = is_top_level
+            .then(|| {
+                let program_id = transaction_context
+                    .get_current_instruction_context()
+                    .and_then(|instr_ctx| instr_ctx.get_last_program_key(transaction_context))
+                    .ok()?;
+                let trace_index = transaction_context
+                    .get_instruction_trace_length()
+                    .saturating_sub(1);
+                Some((trace_index, *program_id))
+            })
+            .flatten();
+        (invoke_context.get_remaining(), top_level_instruction)
+    });
+
+    entrypoint(vm, arg1, arg2, arg3, arg4, arg5);
+
+    with_invoke_context(vm, |invoke_context| {
+        observe_account_resizes(invoke_context.transaction_context);
+        if let Some((trace_index, program_id)) = top_level_instruction {
+            let cu_consumed = cu_before.saturating_sub(invoke_context.get_remaining());
+            profile::record_instruction(trace_index, 1, &program_id, cu_consumed);
+        }
+    });
+}
+
+macro_rules! observed_entrypoints {
+    ($($index:literal)*) => {
+        [$(observed_entrypoint::<$index> as BuiltinFunctionWithContext),*]
+    };
+}
+
+/// One wrapper per builtin entrypoint.
+static OBSERVED_ENTRYPOINTS: [BuiltinFunctionWithContext; 32] = observed_entrypoints!(
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+);
+
+lazy_static! {
+    /// Wrapped entrypoints, indexed by the const parameter of their wrapper
+    static ref ENTRYPOINTS: RwLock<Vec<BuiltinFunctionWithContext>> = RwLock::new(Vec::new());
+}
+
+/// Returns the wrapper observing the instructions executed by the builtin
+/// `entrypoint`. BPF programs are executed by their loader's entrypoint, so
+/// wrapping the loaders observes them as well.
+pub fn observe_entrypoint(entrypoint: BuiltinFunctionWithContext) -> BuiltinFunctionWithContext {
+    if OBSERVED_ENTRYPOINTS
+        .iter()
+        .any(|observed| *observed as usize == entrypoint as usize)
+    {
+        return entrypoint;
+    }
+    let mut entrypoints = ENTRYPOINTS.write().unwrap();
+    let index = match entrypoints
+        .iter()
+        .position(|wrapped| *wrapped as usize == entrypoint as usize)
+    {
+        Some(index) => index,
+        None => {
+            entrypoints.push(entrypoint);
+            entrypoints.len() - 1
+        }
+    };
+    *OBSERVED_ENTRYPOINTS
+        .get(index)
+        .expect("more builtin entrypoints than observed entrypoint wrappers")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_resizes() {
+        let account = Pubkey::new_unique();
+        let other_account = Pubkey::new_unique();
+        let mut resizes = AccountResizes::default();
+
+        resizes.observe(vec![(account, 10), (other_account, 0)]);
+        // Grows within a CPI, then shrinks back once the caller returns
+        resizes.observe(vec![(account, 20), (other_account, 0)]);
+        resizes.observe(vec![(account, 10), (other_account, 5)]);
+        resizes.observe(vec![(account, 10), (other_account, 5)]);
+
+        assert_eq!(
+            resizes.resizes,
+            vec![(account, 10), (account, -10), (other_account, 5)]
+        );
+    }
+}
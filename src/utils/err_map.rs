@@ -7,7 +7,7 @@ use solana_program_runtime::{
     stable_log,
 };
 
-use solana_sdk::{instruction::InstructionError, pubkey::Pubkey};
+use solana_sdk::{instruction::InstructionError, pubkey::Pubkey, transaction::TransactionError};
 
 // Important!
 // The error mapping in this file should be kept aligned with Firedancer.
@@ -22,6 +22,70 @@ pub fn instr_err_to_num(error: &InstructionError) -> i32 {
     i32::from_le_bytes((&serialized_err[0..4]).try_into().unwrap()) + 1
 }
 
+pub fn txn_err_to_num(error: &TransactionError) -> u32 {
+    let err = match error {
+        TransactionError::AccountInUse => 0,
+        TransactionError::AccountLoadedTwice => 1,
+        TransactionError::AccountNotFound => 2,
+        TransactionError::ProgramAccountNotFound => 3,
+        TransactionError::InsufficientFundsForFee => 4,
+        TransactionError::InvalidAccountForFee => 5,
+        TransactionError::AlreadyProcessed => 6,
+        TransactionError::BlockhashNotFound => 7,
+        TransactionError::InstructionError(_, _) => 8,
+        TransactionError::CallChainTooDeep => 9,
+        TransactionError::MissingSignatureForFee => 10,
+        TransactionError::InvalidAccountIndex => 11,
+        TransactionError::SignatureFailure => 12,
+        TransactionError::InvalidProgramForExecution => 13,
+        TransactionError::SanitizeFailure => 14,
+        TransactionError::ClusterMaintenance => 15,
+        TransactionError::AccountBorrowOutstanding => 16,
+        TransactionError::WouldExceedMaxBlockCostLimit => 17,
+        TransactionError::UnsupportedVersion => 18,
+        TransactionError::InvalidWritableAccount => 19,
+        TransactionError::WouldExceedMaxAccountCostLimit => 20,
+        TransactionError::WouldExceedAccountDataBlockLimit => 21,
+        TransactionError::TooManyAccountLocks => 22,
+        TransactionError::AddressLookupTableNotFound => 23,
+        TransactionError::InvalidAddressLookupTableOwner => 24,
+        TransactionError::InvalidAddressLookupTableData => 25,
+        TransactionError::InvalidAddressLookupTableIndex => 26,
+        TransactionError::InvalidRentPayingAccount => 27,
+        TransactionError::WouldExceedMaxVoteCostLimit => 28,
+        TransactionError::WouldExceedAccountDataTotalLimit => 29,
+        TransactionError::DuplicateInstruction(_) => 30,
+        TransactionError::InsufficientFundsForRent { account_index: _ } => 31,
+        TransactionError::MaxLoadedAccountsDataSizeExceeded => 32,
+        TransactionError::InvalidLoadedAccountsDataSizeLimit => 33,
+        TransactionError::ResanitizationNeeded => 34,
+        TransactionError::ProgramExecutionTemporarilyRestricted { account_index: _ } => 35,
+        TransactionError::UnbalancedTransaction => 36,
+        TransactionError::ProgramCacheHitMaxLimit => 37,
+    };
+    err + 1
+}
+
+/// Account index carried by a transaction error, if any.
+pub fn txn_err_account_index(error: &TransactionError) -> Option<u8> {
+    match error {
+        TransactionError::InsufficientFundsForRent { account_index }
+        | TransactionError::ProgramExecutionTemporarilyRestricted { account_index } => {
+            Some(*account_index)
+        }
+        _ => None,
+    }
+}
+
+/// Instruction index carried by a transaction error, if any.
+pub fn txn_err_instruction_index(error: &TransactionError) -> Option<u8> {
+    match error {
+        TransactionError::InstructionError(index, _)
+        | TransactionError::DuplicateInstruction(index) => Some(*index),
+        _ => None,
+    }
+}
+
 pub fn instr_err_to_str(error: &InstructionError) -> String {
     match error {
         // Simplified to: Failed to serialize or deserialize account data
@@ -211,3 +275,92 @@ pub fn unpack_stable_result(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_txn_err_to_num() {
+        assert_eq!(txn_err_to_num(&TransactionError::AccountInUse), 1);
+        assert_eq!(txn_err_to_num(&TransactionError::BlockhashNotFound), 8);
+        assert_eq!(
+            txn_err_to_num(&TransactionError::InstructionError(
+                2,
+                InstructionError::Custom(42)
+            )),
+            9
+        );
+        assert_eq!(txn_err_to_num(&TransactionError::SanitizeFailure), 15);
+        assert_eq!(
+            txn_err_to_num(&TransactionError::DuplicateInstruction(3)),
+            31
+        );
+        assert_eq!(
+            txn_err_to_num(&TransactionError::InsufficientFundsForRent { account_index: 4 }),
+            32
+        );
+        assert_eq!(
+            txn_err_to_num(&TransactionError::ProgramExecutionTemporarilyRestricted {
+                account_index: 5
+            }),
+            36
+        );
+        assert_eq!(
+            txn_err_to_num(&TransactionError::ProgramCacheHitMaxLimit),
+            38
+        );
+    }
+
+    #[test]
+    fn test_txn_err_matches_wire_discriminant() {
+        // The table follows the order of the enum, i.e. its serialized discriminant
+        let errors = [
+            TransactionError::AccountNotFound,
+            TransactionError::InstructionError(1, InstructionError::InvalidArgument),
+            TransactionError::UnsupportedVersion,
+            TransactionError::DuplicateInstruction(7),
+            TransactionError::InsufficientFundsForRent { account_index: 9 },
+            TransactionError::ProgramExecutionTemporarilyRestricted { account_index: 1 },
+            TransactionError::UnbalancedTransaction,
+        ];
+        for error in errors {
+            let serialized = bincode::serialize(&error).unwrap();
+            let discriminant = u32::from_le_bytes(serialized[0..4].try_into().unwrap());
+            assert_eq!(txn_err_to_num(&error), discriminant + 1, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn test_txn_err_payload() {
+        assert_eq!(
+            txn_err_account_index(&TransactionError::InsufficientFundsForRent { account_index: 4 }),
+            Some(4)
+        );
+        assert_eq!(
+            txn_err_account_index(&TransactionError::ProgramExecutionTemporarilyRestricted {
+                account_index: 2
+            }),
+            Some(2)
+        );
+        assert_eq!(
+            txn_err_account_index(&TransactionError::AccountNotFound),
+            None
+        );
+        assert_eq!(
+            txn_err_instruction_index(&TransactionError::DuplicateInstruction(3)),
+            Some(3)
+        );
+        assert_eq!(
+            txn_err_instruction_index(&TransactionError::InstructionError(
+                1,
+                InstructionError::InvalidArgument
+            )),
+            Some(1)
+        );
+        assert_eq!(
+            txn_err_instruction_index(&TransactionError::InsufficientFundsForFee),
+            None
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use crate::proto::{self, ProgramCacheEntryKind};
+use solana_program_runtime::loaded_programs::{
+    LoadProgramMetrics, ProgramCacheEntry, ProgramCacheEntryType, ProgramRuntimeEnvironments,
+};
+use solana_sdk::{
+    account::{AccountSharedData, ReadableAccount},
+    bpf_loader, bpf_loader_deprecated,
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    clock::Slot,
+    loader_v4::{self, LoaderV4State, LoaderV4Status},
+    pubkey::Pubkey,
+};
+use solana_svm::transaction_processing_callback::TransactionProcessingCallback;
+
+/// How an executable account ended up in the program cache used for
+/// execution, and why.
+pub struct LoadedProgramInfo {
+    pub program_id: Pubkey,
+    pub kind: ProgramCacheEntryKind,
+    pub deployment_slot: Slot,
+    pub effective_slot: Slot,
+    pub reason: String,
+}
+
+impl From<LoadedProgramInfo> for proto::LoadedProgram {
+    fn from(value: LoadedProgramInfo) -> Self {
+        proto::LoadedProgram {
+            program_id: value.program_id.to_bytes().to_vec(),
+            kind: value.kind as i32,
+            deployment_slot: value.deployment_slot,
+            effective_slot: value.effective_slot,
+            reason: value.reason,
+        }
+    }
+}
+
+impl LoadedProgramInfo {
+    pub fn not_loaded(program_id: Pubkey, reason: &str) -> Self {
+        Self {
+            program_id,
+            kind: ProgramCacheEntryKind::NotLoaded,
+            deployment_slot: 0,
+            effective_slot: 0,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// Describes `entry`, the program cache entry found for `program_id` at `slot`.
+/// `loaded` is the entry returned by the program loader, which differs from
+/// `entry` when the program is not visible yet.
+pub fn describe_program_cache_entry<CB: TransactionProcessingCallback>(
+    callbacks: &CB,
+    environments: &ProgramRuntimeEnvironments,
+    program_id: &Pubkey,
+    slot: Slot,
+    entry: &ProgramCacheEntry,
+    loaded: &ProgramCacheEntry,
+) -> LoadedProgramInfo {
+    let (kind, reason) = match &entry.program {
+        ProgramCacheEntryType::Loaded(_) => (ProgramCacheEntryKind::Loaded, String::new()),
+        ProgramCacheEntryType::Builtin(_) => (
+            ProgramCacheEntryKind::Builtin,
+            "builtin program".to_string(),
+        ),
+        ProgramCacheEntryType::Unloaded(_) => (
+            ProgramCacheEntryKind::Unloaded,
+            "program was unloaded from the cache".to_string(),
+        ),
+        ProgramCacheEntryType::DelayVisibility => (
+            ProgramCacheEntryKind::DelayVisibility,
+            format!(
+                "program deployed in slot {} is not visible before slot {}, current slot is {}",
+                loaded.deployment_slot, loaded.effective_slot, slot
+            ),
+        ),
+        ProgramCacheEntryType::Closed => (
+            ProgramCacheEntryKind::Closed,
+            explain_closed(callbacks, program_id),
+        ),
+        ProgramCacheEntryType::FailedVerification(_) => (
+            ProgramCacheEntryKind::FailedVerification,
+            explain_failed_verification(callbacks, environments, program_id),
+        ),
+    };
+    LoadedProgramInfo {
+        program_id: *program_id,
+        kind,
+        deployment_slot: loaded.deployment_slot,
+        effective_slot: loaded.effective_slot,
+        reason,
+    }
+}
+
+/// Mirrors the checks of `program_loader::load_program_accounts`, which turns
+/// any invalid program account into a `Closed` tombstone.
+fn explain_closed<CB: TransactionProcessingCallback>(
+    callbacks: &CB,
+    program_id: &Pubkey,
+) -> String {
+    let Some(program_account) = callbacks.get_account_shared_data(program_id) else {
+        return "program account not found".to_string();
+    };
+    let owner = program_account.owner();
+    if loader_v4::check_id(owner) {
+        return match solana_loader_v4_program::get_state(program_account.data()) {
+            Err(_) => "program account data is too small for the loader-v4 state".to_string(),
+            Ok(state) if matches!(state.status, LoaderV4Status::Retracted) => {
+                "loader-v4 program is retracted".to_string()
+            }
+            Ok(_) => "closed by the program loader".to_string(),
+        };
+    }
+    if bpf_loader_upgradeable::check_id(owner) {
+        let Ok(UpgradeableLoaderState::Program {
+            programdata_address,
+        }) = bincode::deserialize(program_account.data())
+        else {
+            return "program account is not in the Program state".to_string();
+        };
+        let Some(programdata_account) = callbacks.get_account_shared_data(&programdata_address)
+        else {
+            return format!("programdata account {} not found", programdata_address);
+        };
+        let Ok(UpgradeableLoaderState::ProgramData { .. }) =
+            bincode::deserialize(programdata_account.data())
+        else {
+            return format!(
+                "programdata account {} is not in the ProgramData state",
+                programdata_address
+            );
+        };
+    }
+    "closed by the program loader".to_string()
+}
+
+/// Reruns the ELF loading and verification the program loader does, to
+/// recover the error it discards when creating a `FailedVerification` tombstone.
+fn explain_failed_verification<CB: TransactionProcessingCallback>(
+    callbacks: &CB,
+    environments: &ProgramRuntimeEnvironments,
+    program_id: &Pubkey,
+) -> String {
+    let Some(program_account) = callbacks.get_account_shared_data(program_id) else {
+        return "program account not found".to_string();
+    };
+    let Some((loader_key, elf_account)) = program_elf_account(callbacks, &program_account) else {
+        return "program data could not be located".to_string();
+    };
+    let (elf_offset, account_size) = if bpf_loader_upgradeable::check_id(&loader_key) {
+        (
+            UpgradeableLoaderState::size_of_programdata_metadata(),
+            program_account.data().len() + elf_account.data().len(),
+        )
+    } else if loader_v4::check_id(&loader_key) {
+        (
+            LoaderV4State::program_data_offset(),
+            program_account.data().len(),
+        )
+    } else {
+        (0, program_account.data().len())
+    };
+    let Some(elf_bytes) = elf_account.data().get(elf_offset..) else {
+        return "program data is shorter than the loader metadata".to_string();
+    };
+    // Loader-v4 programs are loaded in the v2 runtime environment
+    let program_runtime_environment = if loader_v4::check_id(&loader_key) {
+        environments.program_runtime_v2.clone()
+    } else {
+        environments.program_runtime_v1.clone()
+    };
+    match ProgramCacheEntry::new(
+        &loader_key,
+        program_runtime_environment,
+        0,
+        0,
+        elf_bytes,
+        account_size,
+        &mut LoadProgramMetrics::default(),
+    ) {
+        Ok(_) => "verification failed in a different environment".to_string(),
+        Err(err) => err.to_string(),
+    }
+}
+
+/// Returns the loader and the account holding the ELF of a program.
+fn program_elf_account<CB: TransactionProcessingCallback>(
+    callbacks: &CB,
+    program_account: &AccountSharedData,
+) -> Option<(Pubkey, AccountSharedData)> {
+    let owner = *program_account.owner();
+    if bpf_loader_upgradeable::check_id(&owner) {
+        let UpgradeableLoaderState::Program {
+            programdata_address,
+        } = bincode::deserialize(program_account.data()).ok()?
+        else {
+            return None;
+        };
+        let programdata_account = callbacks.get_account_shared_data(&programdata_address)?;
+        return Some((owner, programdata_account));
+    }
+    if bpf_loader::check_id(&owner)
+        || bpf_loader_deprecated::check_id(&owner)
+        || loader_v4::check_id(&owner)
+    {
+        return Some((owner, program_account.clone()));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::account::WritableAccount;
+
+    struct MockCallbacks(Vec<(Pubkey, AccountSharedData)>);
+
+    impl TransactionProcessingCallback for MockCallbacks {
+        fn account_matches_owners(&self, _account: &Pubkey, _owners: &[Pubkey]) -> Option<usize> {
+            None
+        }
+
+        fn get_account_shared_data(&self, pubkey: &Pubkey) -> Option<AccountSharedData> {
+            self.0
+                .iter()
+                .find(|(key, _)| key == pubkey)
+                .map(|(_, account)| account.clone())
+        }
+    }
+
+    fn program_account(owner: &Pubkey, data: Vec<u8>) -> AccountSharedData {
+        let mut account = AccountSharedData::new(1, data.len(), owner);
+        account.set_data_from_slice(&data);
+        account.set_executable(true);
+        account
+    }
+
+    #[test]
+    fn test_explain_failed_verification() {
+        let elf = b"not an ELF".to_vec();
+        let mut loader_v4_data = vec![0; LoaderV4State::program_data_offset()];
+        loader_v4_data.extend_from_slice(&elf);
+
+        for (owner, data) in [
+            (bpf_loader::id(), elf.clone()),
+            (loader_v4::id(), loader_v4_data),
+        ] {
+            let program_id = Pubkey::new_unique();
+            let callbacks = MockCallbacks(vec![(program_id, program_account(&owner, data))]);
+            let reason = explain_failed_verification(
+                &callbacks,
+                &ProgramRuntimeEnvironments::default(),
+                &program_id,
+            );
+            // The loader error is reported, not a loader or environment mismatch
+            assert!(!reason.is_empty());
+            assert_ne!(reason, "program data could not be located");
+            assert_ne!(reason, "verification failed in a different environment");
+        }
+    }
+}
@@ -1,4 +1,9 @@
+pub mod entrypoints;
 pub mod err_map;
+pub mod loaded_programs;
+pub mod profile;
+#[cfg(test)]
+pub(crate) mod test_utils;
 pub mod vm;
 use crate::proto;
 use crate::proto::AcctState;
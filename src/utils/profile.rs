@@ -0,0 +1,321 @@
+use crate::proto;
+use crate::utils::entrypoints::with_invoke_context;
+use solana_program_runtime::{
+    invoke_context::InvokeContext,
+    loaded_programs::ProgramRuntimeEnvironments,
+    solana_rbpf::{
+        program::{BuiltinFunction, BuiltinProgram, FunctionRegistry},
+        vm::EbpfVm,
+    },
+};
+use solana_sdk::pubkey::Pubkey;
+use solana_timings::{ExecuteTimingType, ExecuteTimings};
+use std::{cell::RefCell, collections::BTreeMap, sync::Arc};
+
+type Syscall = BuiltinFunction<InvokeContext<'static>>;
+
+/// Everything recorded by the syscall wrappers of the current execution.
+#[derive(Default)]
+struct ProfileRecorder {
+    /// Whether a profile was started and not taken yet
+    active: bool,
+    /// Wrapped syscalls, indexed by the const parameter of their wrapper
+    syscalls: Vec<(String, Syscall)>,
+    /// Invocation count and charged CUs per syscall name
+    syscall_stats: BTreeMap<String, (u64, u64)>,
+    instructions: Vec<proto::InstrProfile>,
+}
+
+thread_local! {
+    static RECORDER: RefCell<ProfileRecorder> = RefCell::new(ProfileRecorder::default());
+}
+
+/// Calls the syscall registered at `INDEX` and records the CUs it charged. A
+/// syscall growing the instruction trace performed a CPI, which is recorded
+/// as an instruction. Its cost includes the CUs consumed by the callee.
+fn profiled_syscall<const INDEX: usize>(
+    vm: *mut EbpfVm<InvokeContext<'static>>,
+    arg1: u64,
+    arg2: u64,
+    arg3: u64,
+    arg4: u64,
+    arg5: u64,
+) {
+    // The recorder must not stay borrowed across the call, CPIs re-enter it
+    let (name, syscall) = RECORDER.with(|recorder| recorder.borrow().syscalls[INDEX].clone());
+
+    let (cu_before, trace_length_before, stack_height) =
+        with_invoke_context(vm, |invoke_context| {
+            (
+                invoke_context.get_remaining(),
+                invoke_context
+                    .transaction_context
+                    .get_instruction_trace_length(),
+                invoke_context.get_stack_height(),
+            )
+        });
+
+    syscall(vm, arg1, arg2, arg3, arg4, arg5);
+
+    let (cu_charged, cpi_program_id) = with_invoke_context(vm, |invoke_context| {
+        let cu_charged = cu_before.saturating_sub(invoke_context.get_remaining());
+        let transaction_context = &invoke_context.transaction_context;
+        let cpi_program_id = (transaction_context.get_instruction_trace_length()
+            > trace_length_before)
+            .then(|| {
+                transaction_context
+                    .get_instruction_context_at_index_in_trace(trace_length_before)
+                    .and_then(|instr_ctx| instr_ctx.get_last_program_key(transaction_context))
+                    .ok()
+                    .copied()
+            })
+            .flatten();
+        (cu_charged, cpi_program_id)
+    });
+
+    RECORDER.with(|recorder| {
+        let mut recorder = recorder.borrow_mut();
+        let stats = recorder.syscall_stats.entry(name).or_default();
+        stats.0 += 1;
+        stats.1 += cu_charged;
+        if let Some(program_id) = cpi_program_id {
+            recorder.instructions.push(proto::InstrProfile {
+                trace_index: trace_length_before as u64,
+                stack_height: stack_height as u32 + 1,
+                program_id: program_id.to_bytes().to_vec(),
+                cu_consumed: cu_charged,
+            });
+        }
+    });
+}
+
+macro_rules! profiled_syscalls {
+    ($($index:literal)*) => {
+        [$(profiled_syscall::<$index> as Syscall),*]
+    };
+}
+
+/// One wrapper per registered syscall.
+static PROFILED_SYSCALLS: [Syscall; 128] = profiled_syscalls!(
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32 33 34
+    35 36 37 38 39 40 41 42 43 44 45 46 47 48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63 64 65 66
+    67 68 69 70 71 72 73 74 75 76 77 78 79 80 81 82 83 84 85 86 87 88 89 90 91 92 93 94 95 96 97 98
+    99 100 101 102 103 104 105 106 107 108 109 110 111 112 113 114 115 116 117 118 119 120 121 122
+    123 124 125 126 127
+);
+
+/// Returns a copy of `environments` whose syscalls record their invocations,
+/// and starts a new profile. Programs must be loaded with the returned
+/// environments for their syscalls to be profiled.
+///
+/// Panics if more syscalls are registered than there are wrappers, rather than
+/// leaving some of them out of the profile.
+pub fn profile_environments(
+    environments: &ProgramRuntimeEnvironments,
+) -> ProgramRuntimeEnvironments {
+    let program_runtime_v1 = &environments.program_runtime_v1;
+    let mut syscalls = Vec::new();
+    let mut function_registry = FunctionRegistry::<Syscall>::default();
+    for (key, (name, syscall)) in program_runtime_v1.get_function_registry().iter() {
+        let wrapper = *PROFILED_SYSCALLS
+            .get(syscalls.len())
+            .expect("more registered syscalls than profiled syscall wrappers");
+        syscalls.push((String::from_utf8_lossy(name).into_owned(), syscall));
+        function_registry
+            .register_function(key, name, wrapper)
+            .unwrap();
+    }
+
+    RECORDER.with(|recorder| {
+        *recorder.borrow_mut() = ProfileRecorder {
+            active: true,
+            syscalls,
+            ..ProfileRecorder::default()
+        };
+    });
+
+    ProgramRuntimeEnvironments {
+        program_runtime_v1: Arc::new(BuiltinProgram::new_loader(
+            program_runtime_v1.get_config().clone(),
+            function_registry,
+        )),
+        program_runtime_v2: environments.program_runtime_v2.clone(),
+    }
+}
+
+/// Records an instruction which was not invoked through a syscall, i.e. a
+/// top-level instruction. Does nothing unless a profile was started.
+pub(crate) fn record_instruction(
+    trace_index: usize,
+    stack_height: usize,
+    program_id: &Pubkey,
+    cu_consumed: u64,
+) {
+    RECORDER.with(|recorder| {
+        let mut recorder = recorder.borrow_mut();
+        if !recorder.active {
+            return;
+        }
+        recorder.instructions.push(proto::InstrProfile {
+            trace_index: trace_index as u64,
+            stack_height: stack_height as u32,
+            program_id: program_id.to_bytes().to_vec(),
+            cu_consumed,
+        });
+    });
+}
+
+/// Ends the current profile, combining what the syscall wrappers recorded
+/// with the execution timings.
+pub fn take_profile(timings: &ExecuteTimings) -> proto::ExecutionProfile {
+    let recorder = RECORDER.with(|recorder| recorder.take());
+
+    let mut instructions = recorder.instructions;
+    instructions.sort_by_key(|instr| instr.trace_index);
+
+    let syscalls = recorder
+        .syscall_stats
+        .into_iter()
+        .map(|(name, (count, cu_charged))| proto::SyscallProfile {
+            name,
+            count,
+            cu_charged,
+        })
+        .collect();
+
+    let process_instructions = &timings.execute_accessories.process_instructions;
+    let timings_buckets = [
+        ("check_us", timings.metrics[ExecuteTimingType::CheckUs].0),
+        (
+            "validate_fees_us",
+            timings.metrics[ExecuteTimingType::ValidateFeesUs].0,
+        ),
+        ("load_us", timings.metrics[ExecuteTimingType::LoadUs].0),
+        (
+            "execute_us",
+            timings.metrics[ExecuteTimingType::ExecuteUs].0,
+        ),
+        ("store_us", timings.metrics[ExecuteTimingType::StoreUs].0),
+        (
+            "collect_logs_us",
+            timings.metrics[ExecuteTimingType::CollectLogsUs].0,
+        ),
+        (
+            "program_cache_us",
+            timings.metrics[ExecuteTimingType::ProgramCacheUs].0,
+        ),
+        (
+            "process_instructions_total_us",
+            process_instructions.total_us.0,
+        ),
+        ("verify_caller_us", process_instructions.verify_caller_us.0),
+        (
+            "process_executable_chain_us",
+            process_instructions.process_executable_chain_us.0,
+        ),
+        ("verify_callee_us", process_instructions.verify_callee_us.0),
+    ]
+    .into_iter()
+    .map(|(name, value)| proto::TimingBucket {
+        name: name.to_string(),
+        value,
+    })
+    .collect();
+
+    let mut program_timings: Vec<proto::ProgramTiming> = timings
+        .details
+        .per_program_timings
+        .iter()
+        .map(|(program_id, timing)| proto::ProgramTiming {
+            program_id: program_id.to_bytes().to_vec(),
+            accumulated_us: timing.accumulated_us.0,
+            accumulated_units: timing.accumulated_units.0,
+            count: timing.count.0,
+        })
+        .collect();
+    program_timings.sort_by(|a, b| a.program_id.cmp(&b.program_id));
+
+    proto::ExecutionProfile {
+        instructions,
+        syscalls,
+        timings: timings_buckets,
+        program_timings,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_program_runtime_environments;
+    use solana_compute_budget::compute_budget::ComputeBudget;
+    use solana_sdk::feature_set::FeatureSet;
+
+    fn environments() -> ProgramRuntimeEnvironments {
+        create_program_runtime_environments(&FeatureSet::all_enabled(), &ComputeBudget::default())
+    }
+
+    #[test]
+    fn test_profile_environments_wraps_every_syscall() {
+        let environments = environments();
+        let profiled = profile_environments(&environments);
+        let registry = environments.program_runtime_v1.get_function_registry();
+        let profiled_registry = profiled.program_runtime_v1.get_function_registry();
+
+        assert_eq!(profiled_registry.iter().count(), registry.iter().count());
+        for (key, (name, syscall)) in profiled_registry.iter() {
+            assert!(PROFILED_SYSCALLS
+                .iter()
+                .any(|wrapper| *wrapper as usize == syscall as usize));
+            assert_eq!(registry.lookup_by_key(key).unwrap().0, name);
+        }
+        take_profile(&ExecuteTimings::default());
+    }
+
+    #[test]
+    fn test_record_instruction() {
+        let program_id = Pubkey::new_unique();
+        let cpi_program_id = Pubkey::new_unique();
+
+        // Nothing is recorded outside of a profile
+        record_instruction(0, 1, &program_id, 100);
+        assert!(take_profile(&ExecuteTimings::default())
+            .instructions
+            .is_empty());
+
+        profile_environments(&environments());
+        record_instruction(2, 1, &program_id, 300);
+        record_instruction(0, 1, &program_id, 100);
+        record_instruction(1, 2, &cpi_program_id, 50);
+        let profile = take_profile(&ExecuteTimings::default());
+
+        assert_eq!(
+            profile.instructions,
+            vec![
+                proto::InstrProfile {
+                    trace_index: 0,
+                    stack_height: 1,
+                    program_id: program_id.to_bytes().to_vec(),
+                    cu_consumed: 100,
+                },
+                proto::InstrProfile {
+                    trace_index: 1,
+                    stack_height: 2,
+                    program_id: cpi_program_id.to_bytes().to_vec(),
+                    cu_consumed: 50,
+                },
+                proto::InstrProfile {
+                    trace_index: 2,
+                    stack_height: 1,
+                    program_id: program_id.to_bytes().to_vec(),
+                    cu_consumed: 300,
+                },
+            ]
+        );
+
+        // Taking the profile ends it
+        record_instruction(0, 1, &program_id, 100);
+        assert!(take_profile(&ExecuteTimings::default())
+            .instructions
+            .is_empty());
+    }
+}
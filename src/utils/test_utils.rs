@@ -0,0 +1,15 @@
+use crate::proto::AcctState;
+use solana_program::pubkey::Pubkey;
+
+/// Empty account owned by the system program.
+pub(crate) fn system_account(pubkey: &Pubkey, lamports: u64) -> AcctState {
+    AcctState {
+        address: pubkey.to_bytes().to_vec(),
+        lamports,
+        data: vec![],
+        executable: false,
+        rent_epoch: 0,
+        owner: vec![0; 32],
+        seed_addr: None,
+    }
+}
@@ -73,7 +73,7 @@ pub fn copy_memory_prefix(dst: &mut [u8], src: &[u8]) {
     dst[..size].copy_from_slice(&src[..size]);
 }
 
-fn mem_region_to_input_data_region(region: &MemoryRegion) -> InputDataRegion {
+pub fn mem_region_to_input_data_region(region: &MemoryRegion) -> InputDataRegion {
     InputDataRegion {
         content: unsafe {
             std::slice::from_raw_parts(region.host_addr.get() as *const u8, region.len as usize)
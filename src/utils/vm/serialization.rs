@@ -0,0 +1,157 @@
+use crate::proto::{InputDataRegion, SerializedAccountMeta, SerializedInput};
+use crate::utils::vm::mem_regions;
+use solana_bpf_loader_program::serialization::serialize_parameters;
+use solana_program_runtime::invoke_context::SerializedAccountMetadata;
+use solana_program_runtime::solana_rbpf::memory_region::MemoryRegion;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::rent::Rent;
+use solana_sdk::transaction_context::{
+    IndexOfAccount, InstructionAccount, TransactionAccount, TransactionContext,
+};
+
+impl From<&SerializedAccountMetadata> for SerializedAccountMeta {
+    fn from(value: &SerializedAccountMetadata) -> Self {
+        SerializedAccountMeta {
+            original_data_len: value.original_data_len as u64,
+            vm_data_addr: value.vm_data_addr,
+            vm_key_addr: value.vm_key_addr,
+            vm_lamports_addr: value.vm_lamports_addr,
+            vm_owner_addr: value.vm_owner_addr,
+        }
+    }
+}
+
+/* Creates a transaction context with the given instruction pushed onto the
+instruction stack, ready to be serialized by the BPF loader. */
+pub fn create_instruction_transaction_context(
+    transaction_accounts: &[TransactionAccount],
+    rent: Rent,
+    program_idx: IndexOfAccount,
+    instruction_accounts: &[InstructionAccount],
+    instruction_data: &[u8],
+) -> Result<TransactionContext, InstructionError> {
+    let mut transaction_context =
+        TransactionContext::new(transaction_accounts.to_vec(), rent, 1, 1);
+    transaction_context
+        .get_next_instruction_context()?
+        .configure(&[program_idx], instruction_accounts, instruction_data);
+    transaction_context.push()?;
+    Ok(transaction_context)
+}
+
+/* Copies the input data regions out of memory regions created by
+serialize_parameters. Regions point into the parameter bytes and the
+account data, so both must still be alive. */
+pub fn extract_serialized_regions(regions: &[MemoryRegion]) -> Vec<InputDataRegion> {
+    let mut input_data_regions: Vec<InputDataRegion> = regions
+        .iter()
+        .map(mem_regions::mem_region_to_input_data_region)
+        .collect();
+    input_data_regions.sort_by_key(|region| region.offset);
+    input_data_regions
+}
+
+/* Serializes the parameters of the current instruction the way the BPF
+loader does right before creating the VM. Account data is copied into the
+input region unless direct mapping is active. */
+pub fn serialize_instruction_input(
+    transaction_context: &TransactionContext,
+    copy_account_data: bool,
+) -> Result<SerializedInput, InstructionError> {
+    let instruction_context = transaction_context.get_current_instruction_context()?;
+
+    let (_parameter_bytes, regions, account_metadata) =
+        serialize_parameters(transaction_context, instruction_context, copy_account_data)?;
+
+    Ok(SerializedInput {
+        input_data_regions: extract_serialized_regions(&regions),
+        account_metadata: account_metadata.iter().map(Into::into).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program_runtime::solana_rbpf::ebpf::MM_INPUT_START;
+    use solana_sdk::account::{Account, AccountSharedData};
+    use solana_sdk::bpf_loader;
+    use solana_sdk::entrypoint::MAX_PERMITTED_DATA_INCREASE;
+    use solana_sdk::pubkey::Pubkey;
+
+    #[test]
+    fn test_serialize_instruction_input_layout() {
+        let program_id = Pubkey::new_unique();
+        let address = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let transaction_accounts = vec![
+            (
+                program_id,
+                AccountSharedData::from(Account {
+                    lamports: 1,
+                    data: vec![],
+                    owner: bpf_loader::id(),
+                    executable: true,
+                    rent_epoch: 0,
+                }),
+            ),
+            (
+                address,
+                AccountSharedData::from(Account {
+                    lamports: 42,
+                    data: vec![1, 2, 3],
+                    owner,
+                    executable: false,
+                    rent_epoch: 0,
+                }),
+            ),
+        ];
+        let instruction_accounts = [InstructionAccount {
+            index_in_transaction: 1,
+            index_in_caller: 1,
+            index_in_callee: 0,
+            is_signer: true,
+            is_writable: true,
+        }];
+        let transaction_context = create_instruction_transaction_context(
+            &transaction_accounts,
+            Rent::default(),
+            0,
+            &instruction_accounts,
+            &[9, 8],
+        )
+        .unwrap();
+
+        let input = serialize_instruction_input(&transaction_context, true).unwrap();
+
+        // Aligned layout of the BPF loader
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&1u64.to_le_bytes()); // number of accounts
+        expected.extend_from_slice(&[u8::MAX, 1, 1, 0, 0, 0, 0, 0]); // not a duplicate, signer, writable
+        expected.extend_from_slice(address.as_ref());
+        expected.extend_from_slice(owner.as_ref());
+        expected.extend_from_slice(&42u64.to_le_bytes());
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(&[1, 2, 3]);
+        // Realloc space, padded to an 8 byte boundary
+        expected.extend_from_slice(&[0; MAX_PERMITTED_DATA_INCREASE + 5]);
+        expected.extend_from_slice(&0u64.to_le_bytes()); // rent epoch
+        expected.extend_from_slice(&2u64.to_le_bytes());
+        expected.extend_from_slice(&[9, 8]);
+        expected.extend_from_slice(program_id.as_ref());
+
+        assert_eq!(input.input_data_regions.len(), 1);
+        assert_eq!(input.input_data_regions[0].offset, 0);
+        assert!(input.input_data_regions[0].is_writable);
+        assert_eq!(input.input_data_regions[0].content, expected);
+        assert_eq!(
+            input.account_metadata,
+            vec![SerializedAccountMeta {
+                original_data_len: 3,
+                vm_data_addr: MM_INPUT_START + 96,
+                vm_key_addr: MM_INPUT_START + 16,
+                vm_lamports_addr: MM_INPUT_START + 80,
+                vm_owner_addr: MM_INPUT_START + 48,
+            }]
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    load_builtins,
+    dedup_accounts, load_builtins,
     proto::{InstrEffects, SyscallContext, SyscallEffects},
     utils::{
         err_map::unpack_stable_result,
@@ -72,7 +72,12 @@ pub unsafe extern "C" fn sol_compat_vm_cpi_syscall_v1(
 // TODO: unify with other syscall harness after CPI fuzzing is stable
 #[allow(dead_code)]
 fn execute_vm_cpi_syscall(input: SyscallContext) -> Option<SyscallEffects> {
-    let instr_ctx: InstrContext = input.instr_ctx?.try_into().ok()?;
+    let mut instr_ctx: InstrContext = input.instr_ctx?.try_into().ok()?;
+    instr_ctx.accounts = dedup_accounts(
+        std::mem::take(&mut instr_ctx.accounts),
+        instr_ctx.duplicate_account_policy,
+    )
+    .ok()?;
 
     // Create invoke context
     // TODO: factor this into common code with lib.rs
@@ -295,6 +300,7 @@ fn execute_vm_cpi_syscall(input: SyscallContext) -> Option<SyscallEffects> {
             .get_recorded_content()
             .join("\n")
             .into_bytes(),
+        duplicate_account_policy: instr_ctx.duplicate_account_policy as i32,
         ..Default::default()
     })
 }
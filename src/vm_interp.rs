@@ -1,4 +1,5 @@
 use crate::{
+    dedup_accounts,
     proto::{SyscallContext, SyscallEffects, VmContext},
     utils::{
         pchash_inverse,
@@ -90,7 +91,12 @@ pub unsafe extern "C" fn sol_compat_vm_interp_v1(
 
 // We are actually executing the JIT-compiled program here
 fn execute_vm_interp(syscall_context: SyscallContext) -> Option<SyscallEffects> {
-    let instr_ctx: InstrContext = syscall_context.instr_ctx?.try_into().ok()?;
+    let mut instr_ctx: InstrContext = syscall_context.instr_ctx?.try_into().ok()?;
+    instr_ctx.accounts = dedup_accounts(
+        std::mem::take(&mut instr_ctx.accounts),
+        instr_ctx.duplicate_account_policy,
+    )
+    .ok()?;
     let feature_set = instr_ctx.feature_set;
 
     // Load default syscalls, to be stubbed later
@@ -260,6 +266,7 @@ fn execute_vm_interp(syscall_context: SyscallContext) -> Option<SyscallEffects>
             },
             StableResult::Err(_) => vm.registers[11],
         },
+        duplicate_account_policy: instr_ctx.duplicate_account_policy as i32,
         ..Default::default() // FIXME: implement rodata
     })
 }
@@ -1,5 +1,5 @@
 use crate::{
-    load_builtins,
+    dedup_accounts, load_builtins,
     proto::{SyscallContext, SyscallEffects},
     utils::err_map::unpack_stable_result,
     utils::vm::mem_regions,
@@ -63,7 +63,12 @@ pub unsafe extern "C" fn sol_compat_vm_syscall_execute_v1(
 }
 
 fn execute_vm_syscall(input: SyscallContext) -> Option<SyscallEffects> {
-    let instr_ctx: InstrContext = input.instr_ctx?.try_into().ok()?;
+    let mut instr_ctx: InstrContext = input.instr_ctx?.try_into().ok()?;
+    instr_ctx.accounts = dedup_accounts(
+        std::mem::take(&mut instr_ctx.accounts),
+        instr_ctx.duplicate_account_policy,
+    )
+    .ok()?;
 
     let feature_set = instr_ctx.feature_set;
 
@@ -285,5 +290,6 @@ fn execute_vm_syscall(input: SyscallContext) -> Option<SyscallEffects> {
             .join("\n")
             .into_bytes(),
         pc: 0,
+        duplicate_account_policy: instr_ctx.duplicate_account_policy as i32,
     })
 }
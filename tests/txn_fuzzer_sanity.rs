@@ -1,19 +1,28 @@
 use prost::Message;
+use solana_cost_model::block_cost_limits::{SIGNATURE_COST, WRITE_LOCK_UNITS};
 use solana_program::bpf_loader_upgradeable;
 use solana_program::bpf_loader_upgradeable::UpgradeableLoaderState;
 use solana_program::hash::Hash;
 use solana_program::pubkey::Pubkey;
 use solana_sdk::clock::Clock;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
 use solana_sdk::epoch_schedule::EpochSchedule;
+use solana_sdk::nonce::{self, state::DurableNonce};
 use solana_sdk::rent::Rent;
 use solana_sdk::signature::Signature;
+use solana_sdk::sysvar::recent_blockhashes::RecentBlockhashes;
 use solana_sdk::sysvar::SysvarId;
+use solana_sdk::transaction::TransactionError;
 use solana_sdk::{address_lookup_table, feature_set::*};
 use solfuzz_agave::proto::{
     AcctState, CompiledInstruction, EpochContext, FeatureSet, MessageHeader, SanitizedTransaction,
     SlotContext, TransactionMessage, TxnContext, TxnResult,
 };
-use solfuzz_agave::txn_fuzzer::sol_compat_txn_execute_v1;
+use solfuzz_agave::txn_fuzzer::{
+    accounts_db_dir, build_versioned_transaction, remove_accounts_db_dir,
+    set_bank_templates_enabled, sol_compat_txn_execute_v1, MessageBuildError,
+};
+use solfuzz_agave::utils::err_map::txn_err_to_num;
 use solfuzz_agave::{feature_list, proto, utils::feature_u64, HARDCODED_FEATURES};
 use std::fs::File;
 use std::io::{Read, Write};
@@ -158,13 +167,15 @@ fn deploy_program(name: String) -> [(Pubkey, AcctState); 2] {
     ]
 }
 
-#[test]
-fn test_txn_execute_clock() {
+fn clock_program_context(slot: u64) -> TxnContext {
     let clock_sysvar = get_clock_sysvar_account();
     let epoch_schedule = get_epoch_schedule_sysvar_account();
     let rent = get_rent_sysvar_account();
 
-    let slot_ctx = SlotContext { slot: 20 };
+    let slot_ctx = SlotContext {
+        slot,
+        parent_slot: None,
+    };
     let features = get_features();
     let epoch_ctx = EpochContext {
         features: Some(features),
@@ -230,45 +241,44 @@ fn test_txn_execute_clock() {
         signatures: vec![Signature::new_unique().as_ref().to_vec()],
     };
 
-    let txn_input = TxnContext {
+    TxnContext {
         tx: Some(tx),
         max_age: 500,
         blockhash_queue: blockhash_queue,
         epoch_ctx: Some(epoch_ctx),
         slot_ctx: Some(slot_ctx),
-    };
+        duplicate_account_policy: 0,
+        profile: false,
+        log_messages_bytes_limit: None,
+        raw_tx: vec![],
+        verify_signatures: false,
+        fee_structure: None,
+        fee_collector: vec![],
+    }
+}
 
-    let mut buffer: Vec<u8> = txn_input.encode_to_vec();
-    let buffer_len = buffer.len() as u64;
+#[test]
+fn test_txn_execute_clock() {
+    let txn_input = clock_program_context(20);
 
     // Uncomment to write the data to a file
-    // write_to_file("clock-test", &buffer);
-
-    let mut res_buffer: Vec<u8> = vec![0; 512];
-    let mut res_buffer_len = res_buffer.len() as u64;
-    let res = unsafe {
-        sol_compat_txn_execute_v1(
-            res_buffer.as_mut_ptr(),
-            &mut res_buffer_len,
-            buffer.as_mut_ptr(),
-            buffer_len,
-        )
-    };
+    // write_to_file("clock-test", &txn_input.encode_to_vec());
 
-    assert_eq!(res, 1);
-    let result = TxnResult::decode(&res_buffer[..res_buffer_len as usize]).unwrap();
+    let result = execute_txn_context(&txn_input);
     assert!(result.executed);
     assert!(result.is_ok);
     assert_eq!(result.return_data.len(), 8);
 }
 
-#[test]
-fn test_simple_transfer() {
+fn simple_transfer_context() -> (TxnContext, Pubkey, Pubkey) {
     let clock_sysvar = get_clock_sysvar_account();
     let epoch_schedule = get_epoch_schedule_sysvar_account();
     let rent = get_rent_sysvar_account();
 
-    let slot_ctx = SlotContext { slot: 20 };
+    let slot_ctx = SlotContext {
+        slot: 20,
+        parent_slot: None,
+    };
     let features = get_features();
     let epoch_ctx = EpochContext {
         features: Some(features),
@@ -370,14 +380,22 @@ fn test_simple_transfer() {
         blockhash_queue: blockhash_queue,
         epoch_ctx: Some(epoch_ctx),
         slot_ctx: Some(slot_ctx),
+        duplicate_account_policy: 0,
+        profile: false,
+        log_messages_bytes_limit: None,
+        raw_tx: vec![],
+        verify_signatures: false,
+        fee_structure: None,
+        fee_collector: vec![],
     };
 
+    (txn_input, sender, recipient)
+}
+
+fn execute_txn_context(txn_input: &TxnContext) -> TxnResult {
     let mut buffer: Vec<u8> = txn_input.encode_to_vec();
     let buffer_len = buffer.len() as u64;
 
-    // Uncomment to write the data to a file
-    // write_to_file("simple-transfer", &buffer);
-
     let mut res_buffer: Vec<u8> = vec![0; 68007];
     let mut res_buffer_len = res_buffer.len() as u64;
     let res = unsafe {
@@ -390,7 +408,17 @@ fn test_simple_transfer() {
     };
 
     assert_eq!(res, 1);
-    let result = TxnResult::decode(&res_buffer[..res_buffer_len as usize]).unwrap();
+    TxnResult::decode(&res_buffer[..res_buffer_len as usize]).unwrap()
+}
+
+#[test]
+fn test_simple_transfer() {
+    let (txn_input, sender, recipient) = simple_transfer_context();
+
+    // Uncomment to write the data to a file
+    // write_to_file("simple-transfer", &txn_input.encode_to_vec());
+
+    let result = execute_txn_context(&txn_input);
     assert!(result.executed);
     assert!(result.is_ok);
     if let Some(state) = &result.resulting_state {
@@ -404,13 +432,645 @@ fn test_simple_transfer() {
     }
 }
 
+#[test]
+fn test_log_messages() {
+    let (mut txn_input, _, _) = simple_transfer_context();
+    let result = execute_txn_context(&txn_input);
+    assert!(result.is_ok);
+    assert!(result.log_messages.len() > 1);
+    assert!(result.log_messages[0].ends_with("invoke [1]"));
+    assert!(result.loaded_accounts_data_size > 0);
+
+    txn_input.log_messages_bytes_limit = Some(0);
+    let result = execute_txn_context(&txn_input);
+    assert!(result.is_ok);
+    assert_eq!(result.log_messages, vec!["Log truncated".to_string()]);
+}
+
+#[test]
+fn test_profile() {
+    let (mut txn_input, _, _) = simple_transfer_context();
+    txn_input.profile = true;
+    let result = execute_txn_context(&txn_input);
+    assert!(result.is_ok);
+
+    let profile = result.profile.unwrap();
+    assert_eq!(
+        profile.instructions,
+        vec![proto::InstrProfile {
+            trace_index: 0,
+            stack_height: 1,
+            program_id: solana_sdk::system_program::id().to_bytes().to_vec(),
+            cu_consumed: result.executed_units,
+        }]
+    );
+}
+
+#[test]
+fn test_malformed_message() {
+    let sanitize_failure = 15;
+
+    let (mut txn_input, _, _) = simple_transfer_context();
+    let message = txn_input.tx.as_mut().unwrap().message.as_mut().unwrap();
+    message.account_keys[1].truncate(31);
+    let result = execute_txn_context(&txn_input);
+    assert!(result.sanitization_error);
+    assert_eq!(result.status, sanitize_failure);
+    assert_eq!(
+        result.message_build_error,
+        MessageBuildError::InvalidAccountKey as u32
+    );
+
+    let (mut txn_input, _, _) = simple_transfer_context();
+    let message = txn_input.tx.as_mut().unwrap().message.as_mut().unwrap();
+    message.instructions[0].accounts[0] = 257;
+    let result = execute_txn_context(&txn_input);
+    assert!(result.sanitization_error);
+    assert_eq!(result.status, sanitize_failure);
+    assert_eq!(
+        result.message_build_error,
+        MessageBuildError::IndexOutOfRange as u32
+    );
+
+    let (mut txn_input, _, _) = simple_transfer_context();
+    let message = txn_input.tx.as_mut().unwrap().message.as_mut().unwrap();
+    message
+        .header
+        .as_mut()
+        .unwrap()
+        .num_readonly_unsigned_accounts = 256;
+    let result = execute_txn_context(&txn_input);
+    assert!(result.sanitization_error);
+    assert_eq!(result.status, sanitize_failure);
+    assert_eq!(
+        result.message_build_error,
+        MessageBuildError::HeaderCountOutOfRange as u32
+    );
+
+    // Well-formed messages have no message build error
+    let (txn_input, _, _) = simple_transfer_context();
+    let result = execute_txn_context(&txn_input);
+    assert_eq!(result.message_build_error, 0);
+}
+
+#[test]
+fn test_raw_transaction() {
+    let (mut txn_input, sender, _) = simple_transfer_context();
+    let versioned_transaction =
+        build_versioned_transaction(txn_input.tx.as_ref().unwrap()).unwrap();
+    txn_input.raw_tx = bincode::serialize(&versioned_transaction).unwrap();
+    let result = execute_txn_context(&txn_input);
+    assert!(result.executed);
+    assert!(result.is_ok);
+    let sender_state = result
+        .resulting_state
+        .unwrap()
+        .acct_states
+        .into_iter()
+        .find(|account| account.address == sender.to_bytes())
+        .unwrap();
+    assert_eq!(sender_state.lamports, 899990);
+
+    // Account states are optional with a wire-format transaction
+    let mut raw_input = txn_input.clone();
+    raw_input.tx = None;
+    let result = execute_txn_context(&raw_input);
+    assert!(!result.deserialization_error);
+    assert!(!result.executed);
+    assert_eq!(
+        result.status,
+        txn_err_to_num(&TransactionError::AccountNotFound)
+    );
+
+    // Trailing byte
+    let mut trailing_input = txn_input.clone();
+    trailing_input.raw_tx.push(0);
+    let result = execute_txn_context(&trailing_input);
+    assert!(result.deserialization_error);
+    assert!(!result.executed);
+
+    // Truncated packet
+    txn_input.raw_tx.truncate(txn_input.raw_tx.len() / 2);
+    let result = execute_txn_context(&txn_input);
+    assert!(result.deserialization_error);
+    assert!(!result.sanitization_error);
+    assert!(!result.executed);
+}
+
+#[test]
+fn test_verify_signatures() {
+    let (mut txn_input, _, _) = simple_transfer_context();
+    let result = execute_txn_context(&txn_input);
+    assert!(result.is_ok);
+    assert!(!result.signature_failure);
+
+    // The test transaction carries random signatures
+    txn_input.verify_signatures = true;
+    let result = execute_txn_context(&txn_input);
+    assert!(!result.executed);
+    assert!(result.sanitization_error);
+    assert!(result.signature_failure);
+    assert_eq!(result.status, 13); // SignatureFailure
+}
+
+#[test]
+fn test_epoch_boundary() {
+    // With warmup, epoch 0 spans slots 0 to 31
+    let (mut txn_input, _, _) = simple_transfer_context();
+    txn_input.slot_ctx = Some(SlotContext {
+        slot: 32,
+        parent_slot: Some(31),
+    });
+    let result = execute_txn_context(&txn_input);
+    assert!(result.is_ok);
+    let clock_account = result
+        .epoch_sysvars
+        .iter()
+        .find(|account| account.address == Clock::id().to_bytes())
+        .unwrap();
+    let clock: Clock = bincode::deserialize(&clock_account.data).unwrap();
+    assert_eq!(clock.slot, 32);
+    assert_eq!(clock.epoch, 1);
+
+    // The parent slot must precede the slot
+    txn_input.slot_ctx = Some(SlotContext {
+        slot: 32,
+        parent_slot: Some(32),
+    });
+    let mut buffer = txn_input.encode_to_vec();
+    let mut res_buffer: Vec<u8> = vec![0; 68007];
+    let mut res_buffer_len = res_buffer.len() as u64;
+    let res = unsafe {
+        sol_compat_txn_execute_v1(
+            res_buffer.as_mut_ptr(),
+            &mut res_buffer_len,
+            buffer.as_mut_ptr(),
+            buffer.len() as u64,
+        )
+    };
+    assert_eq!(res, 0);
+}
+
+#[test]
+fn test_nonce_transaction() {
+    let payer = Pubkey::new_unique();
+    let nonce = Pubkey::new_unique();
+    let blockhash_queue = vec![
+        Hash::new_unique().to_bytes().to_vec(),
+        Hash::new_unique().to_bytes().to_vec(),
+    ];
+
+    // The stored durable nonce differs from the one derived from the last blockhash
+    let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+    let nonce_data = bincode::serialize(&nonce::state::Versions::new(nonce::State::Initialized(
+        nonce::state::Data::new(payer, durable_nonce, 5000),
+    )))
+    .unwrap();
+    let nonce_state = AcctState {
+        address: nonce.to_bytes().to_vec(),
+        lamports: 10_000_000,
+        data: nonce_data.clone(),
+        executable: false,
+        rent_epoch: 0,
+        owner: vec![0; 32],
+        seed_addr: None,
+    };
+    let payer_state = AcctState {
+        address: payer.to_bytes().to_vec(),
+        lamports: 10_000_000,
+        data: vec![],
+        executable: false,
+        rent_epoch: 0,
+        owner: vec![0; 32],
+        seed_addr: None,
+    };
+
+    // Advances the nonce, then fails transferring more than the payer holds
+    let mut transfer_data = vec![2, 0, 0, 0];
+    transfer_data.extend_from_slice(&u64::MAX.to_le_bytes());
+    let message = TransactionMessage {
+        is_legacy: true,
+        header: Some(MessageHeader {
+            num_required_signatures: 1,
+            num_readonly_signed_accounts: 0,
+            num_readonly_unsigned_accounts: 2,
+        }),
+        account_keys: vec![
+            payer.to_bytes().to_vec(),
+            nonce.to_bytes().to_vec(),
+            RecentBlockhashes::id().to_bytes().to_vec(),
+            vec![0; 32],
+        ],
+        account_shared_data: vec![payer_state, nonce_state],
+        instructions: vec![
+            CompiledInstruction {
+                program_id_index: 3,
+                accounts: vec![1, 2, 0],
+                data: vec![4, 0, 0, 0],
+            },
+            CompiledInstruction {
+                program_id_index: 3,
+                accounts: vec![0, 1],
+                data: transfer_data,
+            },
+        ],
+        address_table_lookups: vec![],
+        recent_blockhash: durable_nonce.as_hash().to_bytes().to_vec(),
+    };
+    let txn_input = TxnContext {
+        tx: Some(SanitizedTransaction {
+            message: Some(message),
+            message_hash: Hash::new_unique().to_bytes().to_vec(),
+            is_simple_vote_tx: false,
+            signatures: vec![Signature::new_unique().as_ref().to_vec()],
+        }),
+        max_age: 150,
+        blockhash_queue,
+        epoch_ctx: Some(EpochContext {
+            features: Some(get_features()),
+        }),
+        slot_ctx: None,
+        duplicate_account_policy: 0,
+        profile: false,
+        log_messages_bytes_limit: None,
+        raw_tx: vec![],
+        verify_signatures: false,
+        fee_structure: None,
+        fee_collector: vec![],
+    };
+
+    let result = execute_txn_context(&txn_input);
+    assert!(result.executed);
+    assert!(!result.is_ok);
+    assert!(result.is_nonce_transaction);
+    let nonce_account = result.nonce_account.unwrap();
+    assert_eq!(nonce_account.address, nonce.to_bytes());
+    assert_ne!(nonce_account.data, nonce_data);
+
+    // The fee payer is charged and the nonce is advanced despite the failure
+    assert_eq!(result.rollback_accounts.len(), 2);
+    assert_eq!(result.rollback_accounts[0].address, payer.to_bytes());
+    assert!(result.rollback_accounts[0].lamports < 10_000_000);
+    assert_eq!(result.rollback_accounts[1], nonce_account);
+}
+
+#[test]
+fn test_fees_only_transaction() {
+    let payer = Pubkey::new_unique();
+    let missing_program = Pubkey::new_unique();
+    let blockhash_queue = vec![Hash::new_unique().to_bytes().to_vec()];
+
+    let mut features = get_features();
+    features
+        .features
+        .push(feature_u64(&enable_transaction_loading_failure_fees::id()));
+
+    // The program account does not exist, loading fails after the fee is charged
+    let message = TransactionMessage {
+        is_legacy: true,
+        header: Some(MessageHeader {
+            num_required_signatures: 1,
+            num_readonly_signed_accounts: 0,
+            num_readonly_unsigned_accounts: 1,
+        }),
+        account_keys: vec![
+            payer.to_bytes().to_vec(),
+            missing_program.to_bytes().to_vec(),
+        ],
+        account_shared_data: vec![AcctState {
+            address: payer.to_bytes().to_vec(),
+            lamports: 10_000_000,
+            data: vec![],
+            executable: false,
+            rent_epoch: 0,
+            owner: vec![0; 32],
+            seed_addr: None,
+        }],
+        instructions: vec![CompiledInstruction {
+            program_id_index: 1,
+            accounts: vec![0],
+            data: vec![],
+        }],
+        address_table_lookups: vec![],
+        recent_blockhash: blockhash_queue[0].clone(),
+    };
+    let txn_input = TxnContext {
+        tx: Some(SanitizedTransaction {
+            message: Some(message),
+            message_hash: Hash::new_unique().to_bytes().to_vec(),
+            is_simple_vote_tx: false,
+            signatures: vec![Signature::new_unique().as_ref().to_vec()],
+        }),
+        max_age: 150,
+        blockhash_queue,
+        epoch_ctx: Some(EpochContext {
+            features: Some(features),
+        }),
+        slot_ctx: None,
+        duplicate_account_policy: 0,
+        profile: false,
+        log_messages_bytes_limit: None,
+        raw_tx: vec![],
+        verify_signatures: false,
+        fee_structure: None,
+        fee_collector: vec![],
+    };
+
+    let result = execute_txn_context(&txn_input);
+    assert!(result.executed);
+    assert!(!result.is_ok);
+    let fee_details = result.fee_details.unwrap();
+    let resulting_state = result.resulting_state.unwrap();
+    assert_eq!(resulting_state.acct_states.len(), 1);
+    assert_eq!(resulting_state.acct_states[0].address, payer.to_bytes());
+    assert_eq!(
+        resulting_state.acct_states[0].lamports,
+        10_000_000 - fee_details.transaction_fee - fee_details.prioritization_fee
+    );
+}
+
+#[test]
+fn test_fee_structure() {
+    let (mut txn_input, _, _) = simple_transfer_context();
+    txn_input.fee_structure = Some(proto::FeeStructure {
+        lamports_per_signature: 10000,
+        lamports_per_write_lock: 0,
+        compute_fee_bins: vec![],
+    });
+    let result = execute_txn_context(&txn_input);
+    assert!(result.is_ok);
+    // Two signatures
+    assert_eq!(result.fee_details.unwrap().transaction_fee, 20000);
+
+    txn_input.fee_structure = Some(proto::FeeStructure {
+        lamports_per_signature: 0,
+        lamports_per_write_lock: 0,
+        compute_fee_bins: vec![],
+    });
+    let result = execute_txn_context(&txn_input);
+    assert!(result.is_ok);
+    assert_eq!(result.fee_details.unwrap().transaction_fee, 0);
+}
+
+#[test]
+fn test_fee_distribution() {
+    // The default collector has no account, a partial fee deposit would leave it rent paying
+    let (mut txn_input, _, _) = simple_transfer_context();
+    let result = execute_txn_context(&txn_input);
+    assert!(result.is_ok);
+    let fee_distribution = result.fee_distribution.unwrap();
+    assert!(fee_distribution.collected_fees > 0);
+    assert_eq!(fee_distribution.rewarded, 0);
+    assert_eq!(fee_distribution.burned, fee_distribution.collected_fees);
+
+    let fee_collector = Pubkey::new_unique();
+    let collector_lamports = 1_000_000_000;
+    txn_input.fee_collector = fee_collector.to_bytes().to_vec();
+    let message = txn_input.tx.as_mut().unwrap().message.as_mut().unwrap();
+    message.account_shared_data.push(AcctState {
+        address: fee_collector.to_bytes().to_vec(),
+        lamports: collector_lamports,
+        data: vec![],
+        executable: false,
+        rent_epoch: 0,
+        owner: vec![0; 32],
+        seed_addr: None,
+    });
+    let result = execute_txn_context(&txn_input);
+    assert!(result.is_ok);
+    let fee_distribution = result.fee_distribution.unwrap();
+    assert_eq!(fee_distribution.fee_collector, fee_collector.to_bytes());
+    // Half of the transaction fee is burned, there is no prioritization fee
+    assert_eq!(
+        fee_distribution.rewarded,
+        fee_distribution.collected_fees / 2
+    );
+    assert_eq!(
+        fee_distribution.burned,
+        fee_distribution.collected_fees - fee_distribution.rewarded
+    );
+    assert_eq!(
+        fee_distribution.collector_balance,
+        collector_lamports + fee_distribution.rewarded
+    );
+
+    // Pay a prioritization fee of 1 lamport per requested compute unit
+    let message = txn_input.tx.as_mut().unwrap().message.as_mut().unwrap();
+    message
+        .header
+        .as_mut()
+        .unwrap()
+        .num_readonly_unsigned_accounts += 1;
+    message
+        .account_keys
+        .push(solana_sdk::compute_budget::id().to_bytes().to_vec());
+    message.instructions.insert(
+        0,
+        CompiledInstruction {
+            program_id_index: 5,
+            accounts: vec![],
+            data: ComputeBudgetInstruction::set_compute_unit_price(1_000_000).data,
+        },
+    );
+    for reward_full_priority_fee_active in [false, true] {
+        if reward_full_priority_fee_active {
+            txn_input
+                .epoch_ctx
+                .as_mut()
+                .unwrap()
+                .features
+                .as_mut()
+                .unwrap()
+                .features
+                .push(feature_u64(&reward_full_priority_fee::id()));
+        }
+        let result = execute_txn_context(&txn_input);
+        assert!(result.is_ok);
+        let fee_details = result.fee_details.unwrap();
+        assert!(fee_details.prioritization_fee > 0);
+        let fee_distribution = result.fee_distribution.unwrap();
+        assert_eq!(
+            fee_distribution.collected_fees,
+            fee_details.transaction_fee + fee_details.prioritization_fee
+        );
+        // Half of the transaction fee is burned once reward_full_priority_fee
+        // is active, half of the whole fee before
+        let rewarded = if reward_full_priority_fee_active {
+            fee_details.transaction_fee / 2 + fee_details.prioritization_fee
+        } else {
+            fee_distribution.collected_fees / 2
+        };
+        assert_eq!(fee_distribution.rewarded, rewarded);
+        assert_eq!(
+            fee_distribution.burned,
+            fee_distribution.collected_fees - rewarded
+        );
+        assert_eq!(
+            fee_distribution.collector_balance,
+            collector_lamports + rewarded
+        );
+    }
+}
+
+#[test]
+fn test_transaction_cost() {
+    let (txn_input, _, _) = simple_transfer_context();
+    let result = execute_txn_context(&txn_input);
+    assert!(result.is_ok);
+    let cost = result.cost.unwrap();
+
+    // One signature, the payer and the recipient are write locked
+    assert_eq!(cost.signature_cost, SIGNATURE_COST);
+    assert_eq!(cost.write_lock_cost, 2 * WRITE_LOCK_UNITS);
+    assert!(cost.programs_execution_cost > 0);
+    assert!(!cost.is_simple_vote);
+    assert_eq!(
+        cost.total_cost,
+        cost.signature_cost
+            + cost.write_lock_cost
+            + cost.data_bytes_cost
+            + cost.programs_execution_cost
+            + cost.loaded_accounts_data_size_cost
+    );
+    // A single transfer fits an empty block
+    assert_eq!(cost.cost_tracker_status, 0);
+}
+
+#[test]
+fn test_executed_transaction_cost() {
+    let (mut txn_input, _, _) = simple_transfer_context();
+    let requested_units = 200_000;
+    let message = txn_input.tx.as_mut().unwrap().message.as_mut().unwrap();
+    message
+        .header
+        .as_mut()
+        .unwrap()
+        .num_readonly_unsigned_accounts += 1;
+    message
+        .account_keys
+        .push(solana_sdk::compute_budget::id().to_bytes().to_vec());
+    message.instructions.insert(
+        0,
+        CompiledInstruction {
+            program_id_index: 5,
+            accounts: vec![],
+            data: ComputeBudgetInstruction::set_compute_unit_limit(requested_units).data,
+        },
+    );
+    let result = execute_txn_context(&txn_input);
+    assert!(result.is_ok);
+    let cost = result.cost.unwrap();
+
+    // The breakdown is costed from the consumed compute units, not the requested ones
+    assert!(result.executed_units < requested_units as u64);
+    assert_eq!(cost.programs_execution_cost, result.executed_units);
+    assert_eq!(
+        cost.total_cost,
+        cost.signature_cost
+            + cost.write_lock_cost
+            + cost.data_bytes_cost
+            + cost.programs_execution_cost
+            + cost.loaded_accounts_data_size_cost
+    );
+    assert_eq!(cost.cost_tracker_status, 0);
+}
+
+#[test]
+fn test_bank_template_isolation() {
+    // The second run forks from the template the first run left behind
+    let (txn_input, _, _) = simple_transfer_context();
+    let first = execute_txn_context(&txn_input);
+    let second = execute_txn_context(&txn_input);
+    assert!(first.is_ok);
+    assert_eq!(first, second);
+
+    set_bank_templates_enabled(false);
+    let fresh = execute_txn_context(&txn_input);
+    set_bank_templates_enabled(true);
+    assert_eq!(first, fresh);
+}
+
+#[test]
+fn test_bank_template_program_across_epochs() {
+    // With warmup, slot 20 is in epoch 0 and slot 40 in epoch 1. Each run
+    // forks the template at a different slot and epoch than the previous one.
+    for slot in [20, 40, 20, 40] {
+        let txn_input = clock_program_context(slot);
+        let result = execute_txn_context(&txn_input);
+        assert!(result.is_ok);
+        assert_eq!(result.return_data.len(), 8);
+
+        set_bank_templates_enabled(false);
+        let fresh = execute_txn_context(&txn_input);
+        set_bank_templates_enabled(true);
+        assert_eq!(result, fresh);
+    }
+}
+
+fn list_files(dir: &std::path::Path, recursive: bool) -> Vec<std::path::PathBuf> {
+    let mut files = vec![];
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if recursive && path.is_dir() {
+                files.extend(list_files(&path, true));
+            }
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Top-level entries of the temporary directories, except the accounts DB
+/// directories of the test threads.
+fn list_temp_files() -> Vec<std::path::PathBuf> {
+    let mut files = vec![];
+    for dir in [std::path::PathBuf::from("/dev/shm"), env::temp_dir()] {
+        files.extend(list_files(&dir, false).into_iter().filter(|path| {
+            !path.file_name().is_some_and(|name| {
+                name.to_string_lossy()
+                    .starts_with("solfuzz-agave-accounts-")
+            })
+        }));
+    }
+    files
+}
+
+#[test]
+fn test_no_files_created() {
+    let (txn_input, _, _) = simple_transfer_context();
+
+    // Each test thread has its own accounts DB directory, created before the
+    // first bank of the thread
+    let accounts_db_dir = accounts_db_dir().unwrap();
+    assert!(accounts_db_dir.starts_with("/dev/shm"));
+    let accounts_db_files = list_files(&accounts_db_dir, true);
+    let temp_files = list_temp_files();
+
+    set_bank_templates_enabled(false);
+    execute_txn_context(&txn_input);
+    set_bank_templates_enabled(true);
+    execute_txn_context(&txn_input);
+    execute_txn_context(&txn_input);
+
+    assert_eq!(list_files(&accounts_db_dir, true), accounts_db_files);
+    assert_eq!(list_temp_files(), temp_files);
+
+    remove_accounts_db_dir();
+    assert!(!accounts_db_dir.exists());
+}
+
 #[test]
 fn test_lookup_table() {
     let clock_sysvar = get_clock_sysvar_account();
     let epoch_schedule = get_epoch_schedule_sysvar_account();
     let rent = get_rent_sysvar_account();
 
-    let slot_ctx = SlotContext { slot: 20 };
+    let slot_ctx = SlotContext {
+        slot: 20,
+        parent_slot: None,
+    };
     let features = get_features();
     let epoch_ctx = EpochContext {
         features: Some(features),
@@ -548,6 +1208,13 @@ fn test_lookup_table() {
         blockhash_queue: blockhash_queue,
         epoch_ctx: Some(epoch_ctx),
         slot_ctx: Some(slot_ctx),
+        duplicate_account_policy: 0,
+        profile: false,
+        log_messages_bytes_limit: None,
+        raw_tx: vec![],
+        verify_signatures: false,
+        fee_structure: None,
+        fee_collector: vec![],
     };
 
     let mut buffer: Vec<u8> = txn_input.encode_to_vec();
@@ -580,4 +1247,63 @@ fn test_lookup_table() {
             }
         }
     }
+    let loaded_addresses = result.loaded_addresses.unwrap();
+    assert_eq!(
+        loaded_addresses.writable,
+        vec![recipient.to_bytes().to_vec()]
+    );
+    assert_eq!(
+        loaded_addresses.readonly,
+        vec![extra_account.to_bytes().to_vec()]
+    );
+    assert_eq!(result.alt_error, proto::AltError::None as i32);
+    let state = result.resulting_state.as_ref().unwrap();
+    let provenance = |pubkey: &Pubkey| {
+        let index = state
+            .acct_states
+            .iter()
+            .position(|account| account.address == pubkey.to_bytes())
+            .unwrap();
+        state.acct_provenance[index]
+    };
+    assert_eq!(state.acct_provenance.len(), state.acct_states.len());
+    assert_eq!(
+        provenance(&sender),
+        proto::AccountProvenance::StaticKey as i32
+    );
+    assert_eq!(
+        provenance(&recipient),
+        proto::AccountProvenance::AltWritable as i32
+    );
+    assert_eq!(
+        provenance(&extra_account),
+        proto::AccountProvenance::AltReadonly as i32
+    );
+
+    // Resolution failures are categorized per lookup table
+    let message =
+        |txn_input: &mut TxnContext| txn_input.tx.as_mut().unwrap().message.as_mut().unwrap();
+    let mut out_of_bounds_input = txn_input.clone();
+    message(&mut out_of_bounds_input).address_table_lookups[0].readonly_indexes = vec![2];
+    let mut invalid_owner_input = txn_input.clone();
+    message(&mut invalid_owner_input).account_shared_data[6].owner = vec![0; 32];
+    let mut invalid_data_input = txn_input.clone();
+    message(&mut invalid_data_input).account_shared_data[6].data = vec![1];
+    let mut missing_table_input = txn_input.clone();
+    message(&mut missing_table_input)
+        .account_shared_data
+        .remove(6);
+
+    for (input, alt_error) in [
+        (out_of_bounds_input, proto::AltError::IndexOutOfBounds),
+        (invalid_owner_input, proto::AltError::InvalidOwner),
+        (invalid_data_input, proto::AltError::InvalidData),
+        (missing_table_input, proto::AltError::MissingTable),
+    ] {
+        let result = execute_txn_context(&input);
+        assert!(result.sanitization_error);
+        assert_eq!(result.alt_error, alt_error as i32);
+        assert_eq!(result.alt_error_lookup_index, 0);
+        assert!(result.loaded_addresses.is_none());
+    }
 }